@@ -0,0 +1,145 @@
+use rand::Rng;
+
+use world::{World, SURFACE_HEIGHT, TILE_SIZE};
+use camera::Camera;
+use fun::Demon;
+use sfx::Sfx;
+use spatial;
+
+/* How long a layer takes to fade fully in or out */
+static FADE_TICKS: f32 = 60.0;
+/* Demons further away than this many tiles aren't heard */
+static GROWL_RANGE: i32 = 8;
+/* Demons standing in less light than this count as unseen */
+static GROWL_MAX_LIGHT: f32 = 0.3;
+/* Tiles within this much support of collapsing creak */
+static CREAK_MARGIN: f32 = 0.3;
+static CREAK_RANGE: i32 = 6;
+
+/* One looping ambient sound, faded towards wherever it should be */
+struct Layer
+{
+	name: &'static str,
+	level: f32,
+	pan: f32,
+}
+
+impl Layer
+{
+	fn new(name: &'static str) -> Layer
+	{
+		Layer
+		{
+			name: name,
+			level: 0.0,
+			pan: 0.0,
+		}
+	}
+
+	fn update(&mut self, sfx: &mut Sfx, target: (f32, f32))
+	{
+		let (gain, pan) = target;
+		let step = 1.0 / FADE_TICKS;
+		if self.level < gain
+		{
+			self.level = (self.level + step).min(gain);
+		}
+		else
+		{
+			self.level = (self.level - step).max(gain);
+		}
+		// Fading out keeps the pan of wherever it was last heard
+		if gain > 0.0
+		{
+			self.pan = pan;
+		}
+		sfx.set_playing_at(self.name, if self.level > 0.0 { Some((self.level, self.pan)) } else { None });
+	}
+}
+
+fn dist_sq(x1: i32, y1: i32, x2: i32, y2: i32) -> i32
+{
+	(x2 - x1) * (x2 - x1) + (y2 - y1) * (y2 - y1)
+}
+
+/* 0 at start, 1 at end and in between linearly */
+fn ramp(value: f32, start: f32, end: f32) -> f32
+{
+	((value - start) / (end - start)).max(0.0).min(1.0)
+}
+
+/* Background sounds that depend on where the listener is: wind near the surface, a low rumble
+ * deep down, growls from demons that are close but in the dark and creaking from tiles about to
+ * give way. Drips are one-shots from random ceilings in view, getting more common with depth. */
+pub struct Ambience
+{
+	wind: Layer,
+	rumble: Layer,
+	growl: Layer,
+	creak: Layer,
+	drip_ticks: i32,
+}
+
+impl Ambience
+{
+	pub fn new() -> Ambience
+	{
+		Ambience
+		{
+			wind: Layer::new("wind"),
+			rumble: Layer::new("rumble"),
+			growl: Layer::new("growl"),
+			creak: Layer::new("creak"),
+			drip_ticks: 0,
+		}
+	}
+
+	pub fn update<R: Rng>(&mut self, world: &World, camera: &Camera, demons: &[Demon], sfx: &mut Sfx, rng: &mut R)
+	{
+		let lx = camera.x + camera.width / 2;
+		let ly = camera.y + camera.height / 2;
+		let depth = (ly / TILE_SIZE - SURFACE_HEIGHT) as f32;
+
+		self.wind.update(sfx, (1.0 - ramp(depth, 2.0, 12.0), 0.0));
+		self.rumble.update(sfx, (ramp(depth, 30.0, 70.0), 0.0));
+
+		let range = GROWL_RANGE * TILE_SIZE;
+		let growl = demons.iter()
+			.filter(|d| !d.dead)
+			.map(|d| (d.x + d.w / 2, d.y + d.h / 2))
+			.filter(|&(x, y)| world.get_light(x, y) < GROWL_MAX_LIGHT && dist_sq(lx, ly, x, y) < range * range)
+			.min_by(|&(x, y)| dist_sq(lx, ly, x, y));
+		self.growl.update(sfx, growl.map_or((0.0, 0.0), |(x, y)| spatial::locate(world, camera, x, y)));
+
+		let creak = world.get_nearest_creaking(lx, ly, CREAK_RANGE, CREAK_MARGIN);
+		self.creak.update(sfx, creak.map_or((0.0, 0.0), |((x, y), strain)|
+		{
+			let (gain, pan) = spatial::locate(world, camera, x, y);
+			(gain * strain, pan)
+		}));
+
+		let drips = ramp(depth, 5.0, 25.0);
+		self.drip_ticks -= 1;
+		if self.drip_ticks <= 0 && drips > 0.0
+		{
+			self.drip_ticks = rng.gen_range(30, 30 + (150.0 * (1.0 - drips)) as i32 + 1);
+			let x = camera.x + rng.gen_range(0, camera.width);
+			let y = camera.y + rng.gen_range(0, camera.height);
+			// Water only drips from a ceiling into open space
+			if !world.colliding(x, y, 1, 1) && world.colliding(x, y - TILE_SIZE, 1, 1)
+			{
+				let (gain, pan) = spatial::locate(world, camera, x, y);
+				sfx.play_at("drip", (gain * drips, pan));
+			}
+		}
+	}
+
+	/* Fades all the loops out, for when the game is over */
+	pub fn fade_out(&mut self, sfx: &mut Sfx)
+	{
+		self.wind.update(sfx, (0.0, 0.0));
+		self.rumble.update(sfx, (0.0, 0.0));
+		self.growl.update(sfx, (0.0, 0.0));
+		self.creak.update(sfx, (0.0, 0.0));
+	}
+}
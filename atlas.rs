@@ -0,0 +1,92 @@
+use allegro5::*;
+
+use std::rc::Rc;
+use std::cmp::max;
+
+static ATLAS_WIDTH: i32 = 512;
+/* Gap between sheets, so filtering doesn't bleed one into another */
+static PADDING: i32 = 1;
+
+/* All the sprite sheets packed into one bitmap, so drawing them doesn't keep switching textures */
+pub struct Atlas
+{
+	bitmap: Rc<Bitmap>,
+	regions: Vec<(~str, i32, i32, i32, i32)>,
+}
+
+fn next_power_of_two(n: i32) -> i32
+{
+	let mut p = 1;
+	while p < n
+	{
+		p *= 2;
+	}
+	p
+}
+
+impl Atlas
+{
+	/* Loads the files and packs them in shelves, tallest first. Files that fail to load are left out. */
+	pub fn build(core: &Core, filenames: &[~str]) -> Atlas
+	{
+		let mut sheets: Vec<(~str, Bitmap)> = Vec::new();
+		for filename in filenames.iter()
+		{
+			if sheets.iter().any(|&(ref f, _)| f == filename)
+			{
+				continue;
+			}
+			match core.load_bitmap(filename.as_slice())
+			{
+				Some(bmp) => sheets.push((filename.clone(), bmp)),
+				None => println!("Could not load {} into the atlas", *filename)
+			}
+		}
+		sheets.sort_by(|&(_, ref a), &(_, ref b)| b.get_height().cmp(&a.get_height()));
+
+		let width = sheets.iter().fold(ATLAS_WIDTH, |w, &(_, ref bmp)| max(w, next_power_of_two(bmp.get_width() + PADDING)));
+		let mut regions = Vec::new();
+		let mut x = 0;
+		let mut y = 0;
+		let mut shelf_height = 0;
+		for &(ref filename, ref bmp) in sheets.iter()
+		{
+			let (w, h) = (bmp.get_width(), bmp.get_height());
+			if x + w > width
+			{
+				x = 0;
+				y += shelf_height + PADDING;
+				shelf_height = 0;
+			}
+			regions.push((filename.clone(), x, y, w, h));
+			x += w + PADDING;
+			shelf_height = max(shelf_height, h);
+		}
+		let height = next_power_of_two(y + shelf_height);
+
+		let bitmap = core.create_bitmap(width, max(height, 1)).expect("Could not create the atlas bitmap");
+		core.set_target_bitmap(&bitmap);
+		core.clear_to_color(core.map_rgba_f(0.0, 0.0, 0.0, 0.0));
+		for (&(_, ref bmp), &(_, x, y, _, _)) in sheets.iter().zip(regions.iter())
+		{
+			core.draw_bitmap(bmp, x as f32, y as f32, Flag::zero());
+		}
+
+		Atlas
+		{
+			bitmap: Rc::new(bitmap),
+			regions: regions,
+		}
+	}
+
+	pub fn get_bitmap(&self) -> Rc<Bitmap>
+	{
+		self.bitmap.clone()
+	}
+
+	/* Where the sheet loaded from filename ended up, as (x, y, w, h) */
+	pub fn get_region(&self, filename: &str) -> Option<(i32, i32, i32, i32)>
+	{
+		self.regions.iter().find(|&&(ref f, _, _, _, _)| f.as_slice() == filename).map(|&(_, x, y, w, h)| (x, y, w, h))
+	}
+}
@@ -0,0 +1,176 @@
+use allegro5::*;
+use allegro_primitives::*;
+
+use rand::Rng;
+use std::f32::consts::PI;
+
+use world::{SURFACE_HEIGHT, TILE_SIZE};
+use camera::Camera;
+
+/* Ticks in a full day, 6 minutes at 60 ticks per second */
+static DAY_LENGTH: i32 = 21600;
+static NUM_STARS: uint = 60;
+
+static NIGHT_SKY: (f32, f32, f32) = (0.02, 0.02, 0.08);
+static DAWN_SKY: (f32, f32, f32) = (0.8, 0.45, 0.3);
+static DAY_SKY: (f32, f32, f32) = (0.45, 0.7, 1.0);
+
+fn lerp_rgb(a: (f32, f32, f32), b: (f32, f32, f32), f: f32) -> (f32, f32, f32)
+{
+	let (ar, ag, ab) = a;
+	let (br, bg, bb) = b;
+	(ar + (br - ar) * f, ag + (bg - ag) * f, ab + (bb - ab) * f)
+}
+
+/* Time of day goes from 0 to 1, with 0 at midnight and 0.5 at noon */
+pub struct DayCycle
+{
+	pub time: f32,
+}
+
+impl DayCycle
+{
+	pub fn new() -> DayCycle
+	{
+		DayCycle
+		{
+			time: 0.35
+		}
+	}
+
+	pub fn update(&mut self)
+	{
+		self.time += 1.0 / DAY_LENGTH as f32;
+		if self.time >= 1.0
+		{
+			self.time -= 1.0;
+		}
+	}
+
+	pub fn get_daylight(&self) -> f32
+	{
+		0.575 - 0.425 * (2.0 * PI * self.time).cos()
+	}
+
+	pub fn get_sky_rgb(&self) -> (f32, f32, f32)
+	{
+		let f = (self.get_daylight() - 0.15) / 0.85;
+		if f < 0.5
+		{
+			lerp_rgb(NIGHT_SKY, DAWN_SKY, f * 2.0)
+		}
+		else
+		{
+			lerp_rgb(DAWN_SKY, DAY_SKY, f * 2.0 - 1.0)
+		}
+	}
+}
+
+struct HillLayer
+{
+	parallax: f32,
+	base: f32,
+	phases: [f32, ..3],
+	color: (f32, f32, f32),
+}
+
+impl HillLayer
+{
+	fn get_height(&self, x: f32) -> f32
+	{
+		self.base + 12.0 * (x * 0.011 + self.phases[0]).sin()
+		          + 6.0 * (x * 0.027 + self.phases[1]).sin()
+		          + 3.0 * (x * 0.063 + self.phases[2]).sin()
+	}
+}
+
+/* The parallax scenery behind the sky tiles */
+pub struct Backdrop
+{
+	layers: Vec<HillLayer>,
+	stars: Vec<(f32, f32)>,
+}
+
+impl Backdrop
+{
+	pub fn new<R: Rng>(rng: &mut R) -> Backdrop
+	{
+		let mut layers = Vec::new();
+		for &(parallax, base, color) in [(0.2f32, 60.0f32, (0.35f32, 0.4f32, 0.55f32)),
+		                                 (0.4, 40.0, (0.25, 0.35, 0.35)),
+		                                 (0.6, 20.0, (0.15, 0.3, 0.15))].iter()
+		{
+			layers.push(HillLayer
+			{
+				parallax: parallax,
+				base: base,
+				phases: [rng.gen_range(0.0, 2.0 * PI), rng.gen_range(0.0, 2.0 * PI), rng.gen_range(0.0, 2.0 * PI)],
+				color: color,
+			});
+		}
+
+		Backdrop
+		{
+			layers: layers,
+			stars: Vec::from_fn(NUM_STARS, |_| (rng.gen_range(0.0f32, 1.0), rng.gen_range(0.0f32, 1.0))),
+		}
+	}
+
+	pub fn draw(&self, core: &Core, prim: &PrimitivesAddon, day: &DayCycle, camera: &Camera)
+	{
+		let horizon = (SURFACE_HEIGHT * TILE_SIZE - camera.y) as f32;
+		if horizon <= 0.0
+		{
+			return;
+		}
+
+		let w = camera.width as f32;
+		let daylight = day.get_daylight();
+
+		let (r, g, b) = day.get_sky_rgb();
+		prim.draw_filled_rectangle(0.0, 0.0, w, horizon, core.map_rgb_f(r, g, b));
+
+		// Stars fade in as the sky darkens
+		let star_alpha = (1.0 - daylight * 1.5).max(0.0);
+		if star_alpha > 0.0
+		{
+			let color = core.map_rgba_f(star_alpha, star_alpha, star_alpha, star_alpha);
+			for &(sx, sy) in self.stars.iter()
+			{
+				let x = (sx * w - camera.x as f32 * 0.05) % w;
+				let x = if x < 0.0 { x + w } else { x };
+				let y = sy * horizon;
+				prim.draw_filled_rectangle(x, y, x + 1.0, y + 1.0, color);
+			}
+		}
+
+		// The sun is up from 0.25 to 0.75, the moon the rest of the time
+		let (angle, radius, color) = if day.time >= 0.25 && day.time < 0.75
+		{
+			((day.time - 0.25) * 2.0 * PI, 8.0, core.map_rgb_f(1.0, 0.95, 0.7))
+		}
+		else
+		{
+			(((day.time + 0.25) % 1.0) * 2.0 * PI, 6.0, core.map_rgb_f(0.85, 0.85, 0.95))
+		};
+		let cx = w / 2.0 - angle.cos() * w * 0.4;
+		let cy = horizon - angle.sin() * horizon * 0.8;
+		prim.draw_filled_circle(cx, cy, radius, color);
+
+		// Hills, back to front
+		for layer in self.layers.iter()
+		{
+			let (r, g, b) = layer.color;
+			let l = 0.2 + 0.8 * daylight;
+			let color = core.map_rgb_f(r * l, g * l, b * l);
+			let offset = camera.x as f32 * layer.parallax;
+			let mut x = 0.0;
+			while x < w
+			{
+				let top = horizon - layer.get_height(x + offset);
+				prim.draw_filled_rectangle(x, top, x + 2.0, horizon, color);
+				x += 2.0;
+			}
+		}
+	}
+}
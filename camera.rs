@@ -1,6 +1,140 @@
-use rand::{task_rng, Rng};
-use std::cmp::{min, max};
+use allegro5::*;
+use rand::Rng;
 
+use config::Config;
+
+pub static ZOOM_LEVELS: &'static [f32] = &[1.0, 1.5, 2.0];
+pub static SHAKE_LEVELS: &'static [f32] = &[1.0, 0.5, 0.0];
+/* How much of the way to the wanted look-ahead and peek offset is covered each tick */
+static LOOK_EASE: f32 = 0.05;
+
+#[deriving(Eq, Clone)]
+pub enum CameraMode
+{
+	/* Snaps to keep the player within the middle third of the view */
+	DeadZoneCamera,
+	/* Glides towards the player every tick */
+	SmoothCamera,
+}
+
+impl CameraMode
+{
+	pub fn get_name(&self) -> &'static str
+	{
+		match *self
+		{
+			DeadZoneCamera => "deadzone",
+			SmoothCamera => "smooth",
+		}
+	}
+
+	pub fn get_description(&self) -> &'static str
+	{
+		match *self
+		{
+			DeadZoneCamera => "Dead zone",
+			SmoothCamera => "Smooth",
+		}
+	}
+}
+
+fn cycle(levels: &[f32], cur: f32, dir: i32) -> f32
+{
+	let n = levels.len() as i32;
+	let idx = match levels.iter().position(|l| *l == cur)
+	{
+		Some(idx) => (idx as i32 + dir + n) % n,
+		None => 0
+	};
+	levels[idx as uint]
+}
+
+/* How the camera follows the player, from the [camera] section of the config:
+ *
+ * mode = deadzone | smooth
+ * smoothing = 0.1
+ * look_ahead = 0
+ * peek = 128
+ * zoom = 1
+ * shake = 1
+ * shake_decay = 0.95
+ *
+ * Smoothing is how much of the way to the player the smooth camera goes each tick. Look-ahead
+ * is how many pixels ahead of the player the view goes in the direction of moving or digging,
+ * peek how far the peek keys look up or down. Shake scales the screen shake, 0 turns it off.
+ */
+#[deriving(Clone)]
+pub struct CameraSettings
+{
+	pub mode: CameraMode,
+	pub smoothing: f32,
+	pub look_ahead: i32,
+	pub peek: i32,
+	pub zoom: f32,
+	pub shake: f32,
+	pub shake_decay: f32,
+}
+
+impl CameraSettings
+{
+	pub fn new() -> CameraSettings
+	{
+		CameraSettings
+		{
+			mode: DeadZoneCamera,
+			smoothing: 0.1,
+			look_ahead: 0,
+			peek: 128,
+			zoom: 1.0,
+			shake: 1.0,
+			shake_decay: 0.95,
+		}
+	}
+
+	pub fn load(config: &Config) -> CameraSettings
+	{
+		let default = CameraSettings::new();
+		CameraSettings
+		{
+			mode: if config.get_str("camera", "mode", "deadzone").as_slice() == "smooth" { SmoothCamera } else { DeadZoneCamera },
+			smoothing: config.get_f32("camera", "smoothing", default.smoothing).max(0.01).min(1.0),
+			look_ahead: config.get_i32("camera", "look_ahead", default.look_ahead),
+			peek: config.get_i32("camera", "peek", default.peek),
+			zoom: config.get_f32("camera", "zoom", default.zoom).max(0.5),
+			shake: config.get_f32("camera", "shake", default.shake).max(0.0),
+			shake_decay: config.get_f32("camera", "shake_decay", default.shake_decay).max(0.0).min(0.99),
+		}
+	}
+
+	pub fn save(&self, config: &mut Config)
+	{
+		config.set("camera", "mode", self.mode.get_name());
+		config.set_f32("camera", "smoothing", self.smoothing);
+		config.set_i32("camera", "look_ahead", self.look_ahead);
+		config.set_i32("camera", "peek", self.peek);
+		config.set_f32("camera", "zoom", self.zoom);
+		config.set_f32("camera", "shake", self.shake);
+		config.set_f32("camera", "shake_decay", self.shake_decay);
+	}
+
+	pub fn toggle_mode(&mut self)
+	{
+		self.mode = if self.mode == DeadZoneCamera { SmoothCamera } else { DeadZoneCamera };
+	}
+
+	pub fn cycle_zoom(&mut self, dir: i32)
+	{
+		self.zoom = cycle(ZOOM_LEVELS, self.zoom, dir);
+	}
+
+	pub fn cycle_shake(&mut self, dir: i32)
+	{
+		self.shake = cycle(SHAKE_LEVELS, self.shake, dir);
+	}
+}
+
+/* The part of the world in view. The position and size are in world pixels, so when zoomed
+ * in the size is smaller than the view buffer. */
 pub struct Camera
 {
 	pub x: i32,
@@ -10,68 +144,167 @@ pub struct Camera
 	pub shake_amp: f32,
 	pub world_width: i32,
 	pub world_height: i32,
+	settings: CameraSettings,
+	view_width: i32,
+	view_height: i32,
+	// Where the camera is before shaking
+	base_x: f32,
+	base_y: f32,
+	look_x: f32,
+	look_y: f32,
 }
 
 impl Camera
 {
-	pub fn new(width: i32, height: i32, world_width: i32, world_height: i32) -> Camera
+	pub fn new(settings: &CameraSettings, view_width: i32, view_height: i32, world_width: i32, world_height: i32) -> Camera
 	{
-		Camera
+		let mut camera = Camera
 		{
 			x: 0,
 			y: 0,
-			width: width,
-			height: height,
+			width: view_width,
+			height: view_height,
 			shake_amp: 0.0,
 			world_width: world_width,
 			world_height: world_height,
+			settings: settings.clone(),
+			view_width: view_width,
+			view_height: view_height,
+			base_x: 0.0,
+			base_y: 0.0,
+			look_x: 0.0,
+			look_y: 0.0,
+		};
+		camera.resize(view_width, view_height);
+		camera
+	}
+
+	pub fn resize(&mut self, view_width: i32, view_height: i32)
+	{
+		self.view_width = view_width;
+		self.view_height = view_height;
+		self.width = (view_width as f32 / self.settings.zoom) as i32;
+		self.height = (view_height as f32 / self.settings.zoom) as i32;
+	}
+
+	pub fn set_settings(&mut self, settings: &CameraSettings)
+	{
+		self.settings = settings.clone();
+		let (w, h) = (self.view_width, self.view_height);
+		self.resize(w, h);
+	}
+
+	/* Scales what's drawn next to the zoom, undone by reset_transform */
+	pub fn use_transform(&self, core: &Core)
+	{
+		let mut t = Transform::identity();
+		t.scale(self.settings.zoom, self.settings.zoom);
+		core.use_transform(&t);
+	}
+
+	pub fn reset_transform(&self, core: &Core)
+	{
+		core.use_transform(&Transform::identity());
+	}
+
+	/* Follows the player, looking ahead in look_dir (e.g. (1, 0) for right) and peeking
+	 * up or down with a peek of -1 or 1 */
+	pub fn update<R: Rng>(&mut self, player_x: i32, player_y: i32, look_dir: (i32, i32), peek: i32, rng: &mut R)
+	{
+		let (look_dx, look_dy) = look_dir;
+		let want_look_x = (look_dx * self.settings.look_ahead) as f32;
+		let want_look_y = (look_dy * self.settings.look_ahead + peek * self.settings.peek) as f32;
+		self.look_x += (want_look_x - self.look_x) * LOOK_EASE;
+		self.look_y += (want_look_y - self.look_y) * LOOK_EASE;
+
+		let new_x = player_x as f32 + self.look_x - (self.width / 2) as f32;
+		let new_y = player_y as f32 + self.look_y - (self.height / 2) as f32;
+
+		match self.settings.mode
+		{
+			DeadZoneCamera =>
+			{
+				let max_dev_x = (self.width / 6) as f32;
+				let max_dev_y = (self.height / 6) as f32;
+
+				if new_x - self.base_x > max_dev_x
+				{
+					self.base_x = new_x - max_dev_x;
+				}
+				else if new_x - self.base_x < -max_dev_x
+				{
+					self.base_x = new_x + max_dev_x;
+				}
+
+				if new_y - self.base_y > max_dev_y
+				{
+					self.base_y = new_y - max_dev_y;
+				}
+				else if new_y - self.base_y < -max_dev_y
+				{
+					self.base_y = new_y + max_dev_y;
+				}
+			},
+			SmoothCamera =>
+			{
+				self.base_x += (new_x - self.base_x) * self.settings.smoothing;
+				self.base_y += (new_y - self.base_y) * self.settings.smoothing;
+			}
 		}
+		self.place(rng);
 	}
 
-	pub fn update(&mut self, player_x: i32, player_y: i32)
+	/* Where the middle of the view is, leaving out the shaking */
+	pub fn get_center(&self) -> (f32, f32)
 	{
-		let (jolt_x, jolt_y) = if self.shake_amp > 0.0
+		(self.base_x + (self.width / 2) as f32, self.base_y + (self.height / 2) as f32)
+	}
+
+	/* Puts the middle of the view at x, y instead of following the player, for cutscenes */
+	pub fn center_on<R: Rng>(&mut self, x: f32, y: f32, rng: &mut R)
+	{
+		self.base_x = x - (self.width / 2) as f32;
+		self.base_y = y - (self.height / 2) as f32;
+		self.place(rng);
+	}
+
+	/* Keeps the view inside the world and shakes it */
+	fn place<R: Rng>(&mut self, rng: &mut R)
+	{
+		self.shake_amp *= self.settings.shake_decay;
+		let amp = self.shake_amp * self.settings.shake;
+		let (jolt_x, jolt_y) = if amp > 0.5
 		{
-			self.shake_amp *= 0.95;
-			(task_rng().gen_range(-self.shake_amp, self.shake_amp) as i32,
-			 task_rng().gen_range(-self.shake_amp, self.shake_amp) as i32)
+			(rng.gen_range(-amp, amp) as i32,
+			 rng.gen_range(-amp, amp) as i32)
 		}
 		else
 		{
 			(0, 0)
 		};
-		
-		let new_x = player_x - self.width / 2;
-		let new_y = player_y - self.height / 2;
-		
-		let max_dev_x = self.width / 6;
-		let max_dev_y = self.height / 6;
-		
-		if new_x - self.x > max_dev_x
+
+		// Center the world if the view is bigger than it
+		self.base_x = if self.width < self.world_width
 		{
-			self.x = new_x - max_dev_x;
+			self.base_x.max(0.0).min((self.world_width - self.width) as f32)
 		}
-		else if new_x - self.x < -max_dev_x
+		else
 		{
-			self.x = new_x + max_dev_x;
-		}
-
-		if new_y - self.y > max_dev_y
+			((self.world_width - self.width) / 2) as f32
+		};
+		self.base_y = if self.height < self.world_height
 		{
-			self.y = new_y - max_dev_y;
+			self.base_y.max(0.0).min((self.world_height - self.height) as f32)
 		}
-		else if new_y - self.y < -max_dev_y
+		else
 		{
-			self.y = new_y + max_dev_y;
-		}
-		
-		self.x = min(max(self.x, 0), self.world_width - self.width);
-		self.y = min(max(self.y, 0), self.world_height - self.height);
+			((self.world_height - self.height) / 2) as f32
+		};
 
-		self.x += jolt_x;
-		self.y += jolt_y;
+		self.x = self.base_x as i32 + jolt_x;
+		self.y = self.base_y as i32 + jolt_y;
 	}
-	
+
 	pub fn jolt(&mut self, amount: f32)
 	{
 		self.shake_amp = self.shake_amp.max(amount);
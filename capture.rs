@@ -0,0 +1,215 @@
+use allegro5::*;
+use allegro_font::*;
+
+use std::io::{File, UserDir};
+use std::io::fs::mkdir_recursive;
+use time;
+
+use lang::Strings;
+use png;
+
+static SCREENSHOT_DIR: &'static str = "screenshots";
+static RECORDING_DIR: &'static str = "recordings";
+/* Recordings keep every other frame, for 30 fps */
+static RECORD_EVERY: i32 = 2;
+/* Recording stops by itself after this many frames, a minute's worth */
+static MAX_RECORD_FRAMES: i32 = 1800;
+static NOTICE_TICKS: i32 = 120;
+
+/* Where a frame goes, its size and its RGB pixels */
+type Frame = (Path, uint, uint, Vec<u8>);
+
+struct Recording
+{
+	dir: Path,
+	frames: i32,
+	ticks: i32,
+	// The frame is copied here to read its pixels, which is slow straight from the video bitmap
+	copy: Option<Bitmap>,
+	// Encoding and saving a PNG takes longer than a tick, so a task does it. It finishes the
+	// frames it has been sent after the recording stops.
+	encoder: Sender<Frame>,
+}
+
+/* Screenshots and image sequence recordings of the view buffer */
+pub struct Capture
+{
+	recording: Option<Recording>,
+	notice: Option<(~str, i32)>,
+	// Keeps captures made within the same second apart
+	count: uint,
+}
+
+fn start_encoder() -> Sender<Frame>
+{
+	let (sender, receiver) = channel::<Frame>();
+	spawn(proc()
+	{
+		let encoder = png::Encoder::new();
+		for (path, width, height, pixels) in receiver.iter()
+		{
+			let data = encoder.encode(width, height, pixels.as_slice());
+			match File::create(&path).write(data.as_slice())
+			{
+				Ok(_) => (),
+				Err(e) => println!("Failed to save {}: {}", path.display(), e)
+			}
+		}
+	});
+	sender
+}
+
+fn read_pixels(bitmap: &Bitmap) -> Vec<u8>
+{
+	let (width, height) = (bitmap.get_width(), bitmap.get_height());
+	let mut pixels = Vec::with_capacity((width * height * 3) as uint);
+	for y in range(0, height)
+	{
+		for x in range(0, width)
+		{
+			let (r, g, b) = bitmap.get_pixel(x, y).unmap_rgb();
+			pixels.push(r);
+			pixels.push(g);
+			pixels.push(b);
+		}
+	}
+	pixels
+}
+
+fn make_dir(path: &Path) -> bool
+{
+	match mkdir_recursive(path, UserDir)
+	{
+		Ok(_) => true,
+		Err(e) =>
+		{
+			println!("Failed to create {}: {}", path.display(), e);
+			false
+		}
+	}
+}
+
+impl Capture
+{
+	pub fn new() -> Capture
+	{
+		Capture
+		{
+			recording: None,
+			notice: None,
+			count: 0,
+		}
+	}
+
+	/* A name that hasn't been used yet */
+	fn next_name(&mut self) -> ~str
+	{
+		self.count += 1;
+		format!("repercussion-{}-{}", time::now().strftime("%Y%m%d-%H%M%S"), self.count)
+	}
+
+	fn notify(&mut self, text: ~str)
+	{
+		self.notice = Some((text, NOTICE_TICKS));
+	}
+
+	pub fn screenshot(&mut self, core: &Core, buffer: &Bitmap, strings: &Strings)
+	{
+		let dir = Path::new(SCREENSHOT_DIR);
+		if !make_dir(&dir)
+		{
+			return;
+		}
+		let path = dir.join(format!("{}.png", self.next_name()));
+		let filename = path.as_str().unwrap_or("");
+		if core.save_bitmap(filename, buffer)
+		{
+			self.notify(strings.fill("Saved {1}", [filename]));
+		}
+		else
+		{
+			self.notify(strings.fill("Could not save {1}", [filename]));
+		}
+	}
+
+	pub fn toggle_recording(&mut self, strings: &Strings)
+	{
+		match self.recording.take()
+		{
+			Some(rec) => self.notify(strings.fill("Recorded {1} frames to {2}", [rec.frames.to_str().as_slice(), rec.dir.as_str().unwrap_or("")])),
+			None =>
+			{
+				let dir = Path::new(RECORDING_DIR).join(self.next_name());
+				if make_dir(&dir)
+				{
+					self.recording = Some(Recording
+					{
+						dir: dir,
+						frames: 0,
+						ticks: 0,
+						copy: None,
+						encoder: start_encoder(),
+					});
+				}
+			}
+		}
+	}
+
+	/* Called once per tick with the finished frame */
+	pub fn update(&mut self, core: &Core, buffer: &Bitmap, strings: &Strings)
+	{
+		self.notice = match self.notice.take()
+		{
+			Some((text, ticks)) if ticks > 1 => Some((text, ticks - 1)),
+			_ => None
+		};
+
+		let full = match self.recording
+		{
+			Some(ref mut rec) =>
+			{
+				if rec.ticks % RECORD_EVERY == 0
+				{
+					if rec.copy.is_none()
+					{
+						let flags = core.get_new_bitmap_flags();
+						core.set_new_bitmap_flags(MEMORY_BITMAP);
+						rec.copy = core.create_bitmap(buffer.get_width(), buffer.get_height());
+						core.set_new_bitmap_flags(flags);
+					}
+					match rec.copy
+					{
+						Some(ref copy) =>
+						{
+							core.set_target_bitmap(copy);
+							core.draw_bitmap(buffer, 0.0, 0.0, Flag::zero());
+							let path = rec.dir.join(format!("frame-{:05d}.png", rec.frames));
+							rec.encoder.send((path, copy.get_width() as uint, copy.get_height() as uint, read_pixels(copy)));
+							rec.frames += 1;
+						},
+						None => ()
+					}
+				}
+				rec.ticks += 1;
+				rec.frames >= MAX_RECORD_FRAMES
+			},
+			None => false
+		};
+		if full
+		{
+			self.toggle_recording(strings);
+		}
+	}
+
+	pub fn draw(&self, core: &Core, font: &Font, dw: i32)
+	{
+		if self.recording.is_some()
+		{
+			core.draw_text(font, core.map_rgb_f(1.0, 0.2, 0.2), (dw - 10) as f32, 10.0, AlignRight, "REC");
+		}
+		self.notice.as_ref().map(|&(ref text, _)|
+		{
+			core.draw_text(font, core.map_rgb_f(1.0, 1.0, 1.0), (dw / 2) as f32, 24.0, AlignCentre, text.as_slice());
+		});
+	}
+}
@@ -0,0 +1,165 @@
+use std::io::File;
+
+/* A simple INI-like file:
+ *
+ * # comment
+ * [section]
+ * key = value
+ */
+pub struct Config
+{
+	entries: Vec<(~str, ~str, ~str)>,
+}
+
+impl Config
+{
+	pub fn new() -> Config
+	{
+		Config
+		{
+			entries: Vec::new()
+		}
+	}
+
+	pub fn load(filename: &str) -> Option<Config>
+	{
+		File::open(&Path::new(filename)).read_to_str().ok().map(|text| Config::parse(text))
+	}
+
+	pub fn parse(text: &str) -> Config
+	{
+		let mut config = Config::new();
+		let mut section = ~"";
+		for line in text.lines()
+		{
+			let line = line.trim();
+			if line.len() == 0 || line.starts_with("#")
+			{
+				continue;
+			}
+
+			if line.starts_with("[") && line.ends_with("]")
+			{
+				section = line.slice(1, line.len() - 1).trim().to_owned();
+				continue;
+			}
+
+			match line.find('=')
+			{
+				Some(idx) => config.set(section.as_slice(), line.slice_to(idx).trim(), line.slice_from(idx + 1).trim()),
+				None => println!("Ignoring malformed config line: {}", line)
+			}
+		}
+		config
+	}
+
+	pub fn save(&self, filename: &str) -> bool
+	{
+		let mut text = ~"";
+		for section in self.get_sections().iter()
+		{
+			text = text + format!("[{}]\n", *section);
+			for &(ref s, ref k, ref v) in self.entries.iter()
+			{
+				if s.as_slice() == section.as_slice()
+				{
+					text = text + format!("{} = {}\n", *k, *v);
+				}
+			}
+			text = text + "\n";
+		}
+
+		match File::create(&Path::new(filename)).write_str(text)
+		{
+			Ok(_) => true,
+			Err(e) =>
+			{
+				println!("Failed to save {}: {}", filename, e);
+				false
+			}
+		}
+	}
+
+	pub fn get_sections(&self) -> Vec<~str>
+	{
+		let mut sections: Vec<~str> = Vec::new();
+		for &(ref s, _, _) in self.entries.iter()
+		{
+			if !sections.iter().any(|s2| s2.as_slice() == s.as_slice())
+			{
+				sections.push(s.clone());
+			}
+		}
+		sections
+	}
+
+	pub fn get_keys(&self, section: &str) -> Vec<~str>
+	{
+		self.entries.iter().filter(|&&(ref s, _, _)| s.as_slice() == section).map(|&(_, ref k, _)| k.clone()).collect()
+	}
+
+	pub fn get<'l>(&'l self, section: &str, key: &str) -> Option<&'l str>
+	{
+		for &(ref s, ref k, ref v) in self.entries.iter()
+		{
+			if s.as_slice() == section && k.as_slice() == key
+			{
+				return Some(v.as_slice());
+			}
+		}
+		None
+	}
+
+	pub fn set(&mut self, section: &str, key: &str, value: &str)
+	{
+		for &(ref s, ref k, ref mut v) in self.entries.mut_iter()
+		{
+			if s.as_slice() == section && k.as_slice() == key
+			{
+				*v = value.to_owned();
+				return;
+			}
+		}
+		self.entries.push((section.to_owned(), key.to_owned(), value.to_owned()));
+	}
+
+	pub fn get_str(&self, section: &str, key: &str, default: &str) -> ~str
+	{
+		self.get(section, key).unwrap_or(default).to_owned()
+	}
+
+	pub fn get_f32(&self, section: &str, key: &str, default: f32) -> f32
+	{
+		self.get(section, key).and_then(|v| from_str::<f32>(v)).unwrap_or(default)
+	}
+
+	pub fn get_i32(&self, section: &str, key: &str, default: i32) -> i32
+	{
+		self.get(section, key).and_then(|v| from_str::<i32>(v)).unwrap_or(default)
+	}
+
+	pub fn get_bool(&self, section: &str, key: &str, default: bool) -> bool
+	{
+		match self.get(section, key)
+		{
+			Some("true") | Some("yes") | Some("1") => true,
+			Some("false") | Some("no") | Some("0") => false,
+			_ => default
+		}
+	}
+
+	pub fn set_f32(&mut self, section: &str, key: &str, value: f32)
+	{
+		self.set(section, key, format!("{}", value).as_slice());
+	}
+
+	pub fn set_i32(&mut self, section: &str, key: &str, value: i32)
+	{
+		self.set(section, key, format!("{}", value).as_slice());
+	}
+
+	pub fn set_bool(&mut self, section: &str, key: &str, value: bool)
+	{
+		self.set(section, key, if value { "true" } else { "false" });
+	}
+}
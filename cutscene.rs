@@ -0,0 +1,346 @@
+use std::mem::replace;
+
+use config::Config;
+use dialogue::Trigger;
+use entity::Player;
+use game::Game;
+use sfx::Sfx;
+use world::{SURFACE_HEIGHT, TILE_SIZE};
+
+pub static CUTSCENE_DEFS: &'static str = "data/cutscenes.cfg";
+
+/* Somewhere the camera can pan to */
+#[deriving(Clone)]
+enum Target
+{
+	PlayerTarget,
+	StoneTarget,
+	SurfaceTarget,
+	DemonTarget,
+}
+
+impl Target
+{
+	fn from_str(s: &str) -> Option<Target>
+	{
+		match s
+		{
+			"player" => Some(PlayerTarget),
+			"stone" => Some(StoneTarget),
+			"surface" => Some(SurfaceTarget),
+			"demon" => Some(DemonTarget),
+			_ => None
+		}
+	}
+
+	/* Where it is in world pixels. Anything that isn't there any more is where the player is. */
+	fn locate(&self, game: &Game) -> (f32, f32)
+	{
+		let px = game.player.x + game.player.w / 2;
+		let py = game.player.y + game.player.h / 2;
+		let (x, y) = match *self
+		{
+			PlayerTarget => (px, py),
+			StoneTarget if !game.phil.dead => (game.phil.x + game.phil.w / 2, game.phil.y + game.phil.h / 2),
+			StoneTarget => (px, py),
+			SurfaceTarget => (px, SURFACE_HEIGHT * TILE_SIZE),
+			DemonTarget => game.demons.iter()
+				.filter(|d| !d.dead)
+				.map(|d| (d.x + d.w / 2, d.y + d.h / 2))
+				.min_by(|&(x, y)| (x - px) * (x - px) + (y - py) * (y - py))
+				.unwrap_or((px, py))
+		};
+		(x as f32, y as f32)
+	}
+}
+
+/* What a cutscene can do to the player */
+#[deriving(Clone)]
+enum PlayerChange
+{
+	BecomeDemon,
+	BecomeHuman,
+	FaceLeft,
+	FaceRight,
+}
+
+#[deriving(Clone)]
+enum Step
+{
+	// Ticks, where to and an offset in tiles
+	PanStep(i32, Target, i32, i32),
+	WaitStep(i32),
+	SayStep(~str),
+	SoundStep(~str),
+	JoltStep(f32),
+	PlayerStep(PlayerChange),
+}
+
+fn int_arg(words: &[&str], i: uint) -> Option<i32>
+{
+	words.get(i).and_then(|w| from_str::<i32>(*w))
+}
+
+impl Step
+{
+	/* Parses e.g. `pan 60 stone 0 -2` or `say found` */
+	fn from_str(s: &str) -> Option<Step>
+	{
+		let words: Vec<&str> = s.words().collect();
+		let words = words.as_slice();
+		match (words.get(0).map(|w| *w), words.len())
+		{
+			(Some("pan"), 3) | (Some("pan"), 5) =>
+			{
+				let offset = if words.len() == 5 { (int_arg(words, 3), int_arg(words, 4)) } else { (Some(0), Some(0)) };
+				match (int_arg(words, 1), Target::from_str(words[2]), offset)
+				{
+					(Some(ticks), Some(target), (Some(dx), Some(dy))) => Some(PanStep(ticks.max(1), target, dx, dy)),
+					_ => None
+				}
+			},
+			(Some("wait"), 2) => int_arg(words, 1).map(|ticks| WaitStep(ticks)),
+			(Some("say"), 2) => Some(SayStep(words[1].to_owned())),
+			(Some("sound"), 2) => Some(SoundStep(words[1].to_owned())),
+			(Some("jolt"), 2) => from_str::<f32>(words[1]).map(|amount| JoltStep(amount)),
+			(Some("player"), 2) => match words[1]
+			{
+				"demon" => Some(PlayerStep(BecomeDemon)),
+				"human" => Some(PlayerStep(BecomeHuman)),
+				"left" => Some(PlayerStep(FaceLeft)),
+				"right" => Some(PlayerStep(FaceRight)),
+				_ => None
+			},
+			_ => None
+		}
+	}
+}
+
+struct Def
+{
+	name: ~str,
+	trigger: Trigger,
+	steps: Vec<Step>,
+}
+
+impl Def
+{
+	fn load(defs: &Config, name: &str) -> Option<Def>
+	{
+		let trigger_str = defs.get_str(name, "trigger", "");
+		let trigger = match Trigger::from_str(trigger_str.as_slice())
+		{
+			Some(trigger) => trigger,
+			None =>
+			{
+				println!("Unknown trigger '{}' for cutscene {}", trigger_str, name);
+				return None;
+			}
+		};
+
+		let mut steps = Vec::new();
+		for step_str in defs.get_str(name, "steps", "").split('|').map(|s| s.trim()).filter(|s| s.len() > 0)
+		{
+			match Step::from_str(step_str)
+			{
+				Some(step) => steps.push(step),
+				None => println!("Ignoring bad step '{}' in cutscene {}", step_str, name)
+			}
+		}
+
+		Some(Def
+		{
+			name: name.to_owned(),
+			trigger: trigger,
+			steps: steps,
+		})
+	}
+}
+
+/* The cutscenes, from data/cutscenes.cfg with one section each:
+ *
+ * [found]
+ * trigger = stone_found
+ * steps = jolt 4 | say found | pan 120 surface | wait 60 | pan 90 player
+ *
+ * Triggers are the same as for dialogue. The steps run one after the other:
+ *
+ * pan <ticks> <player | stone | surface | demon> [<dx> <dy>]  moves the camera there, the offset is in tiles
+ * wait <ticks>
+ * say <message>     shows a message from data/dialogue.cfg and waits until it's gone
+ * sound <name>      plays a sound from data/sounds.cfg
+ * jolt <amount>     shakes the camera
+ * player <demon | human | left | right>  changes what the player is or which way they face
+ *
+ * The world stands still while a cutscene plays, and each one plays at most once. A message the
+ * cutscene says is left for it to show instead of appearing as soon as its trigger happens.
+ */
+pub struct Cutscenes
+{
+	defs: Vec<Def>,
+	played: Vec<~str>,
+}
+
+impl Cutscenes
+{
+	/* None at all */
+	pub fn new() -> Cutscenes
+	{
+		Cutscenes
+		{
+			defs: Vec::new(),
+			played: Vec::new(),
+		}
+	}
+
+	pub fn load(filename: &str) -> Cutscenes
+	{
+		let defs = Config::load(filename).unwrap_or_else(||
+		{
+			println!("Could not load {}, there will be no cutscenes", filename);
+			Config::new()
+		});
+		Cutscenes
+		{
+			defs: defs.get_sections().iter().filter_map(|name| Def::load(&defs, name.as_slice())).collect(),
+			played: Vec::new(),
+		}
+	}
+
+	/* Rereads the cutscenes, remembering which have been played */
+	pub fn reload(&mut self, filename: &str)
+	{
+		let played = replace(&mut self.played, Vec::new());
+		*self = Cutscenes::load(filename);
+		self.played = played;
+	}
+
+	/* The cutscene waiting for this trigger, if there is one that hasn't been played yet */
+	pub fn start(&mut self, trigger: &Trigger) -> Option<Cutscene>
+	{
+		let def = match self.defs.iter().find(|d| d.trigger.matches(trigger) && !self.played.contains(&d.name))
+		{
+			Some(def) => def,
+			None => return None
+		};
+		self.played.push(def.name.clone());
+		Some(Cutscene
+		{
+			steps: def.steps.clone(),
+			step: 0,
+			ticks: 0,
+			pan_from: (0.0, 0.0),
+			skipping: false,
+		})
+	}
+}
+
+/* A cutscene that is playing */
+pub struct Cutscene
+{
+	steps: Vec<Step>,
+	step: uint,
+	// Into the current step
+	ticks: i32,
+	pan_from: (f32, f32),
+	skipping: bool,
+}
+
+impl Cutscene
+{
+	/* Whether one of the steps says this message */
+	pub fn says(&self, name: &str) -> bool
+	{
+		self.steps.iter().any(|step| match *step
+		{
+			SayStep(ref n) => n.as_slice() == name,
+			_ => false
+		})
+	}
+
+	/* Hurries through the rest, leaving out the messages and shaking */
+	pub fn skip(&mut self)
+	{
+		self.skipping = true;
+	}
+
+	/* Runs the steps for a tick, returning false once they're all done */
+	pub fn update(&mut self, game: &mut Game, sfx: &mut Sfx) -> bool
+	{
+		while self.step < self.steps.len()
+		{
+			if !self.run_step(game, sfx)
+			{
+				return true;
+			}
+			self.step += 1;
+			self.ticks = 0;
+		}
+		false
+	}
+
+	/* Returns whether the current step is done */
+	fn run_step(&mut self, game: &mut Game, sfx: &mut Sfx) -> bool
+	{
+		let first = self.ticks == 0;
+		self.ticks += 1;
+		match self.steps.get(self.step).clone()
+		{
+			PanStep(ticks, target, dx, dy) =>
+			{
+				if first
+				{
+					self.pan_from = game.camera.get_center();
+				}
+				let (to_x, to_y) = target.locate(game);
+				let to_x = to_x + (dx * TILE_SIZE) as f32;
+				let to_y = to_y + (dy * TILE_SIZE) as f32;
+				let t = if self.skipping { 1.0 } else { (self.ticks as f32 / ticks as f32).min(1.0) };
+				// Eased in and out
+				let s = t * t * (3.0 - 2.0 * t);
+				let (from_x, from_y) = self.pan_from;
+				game.camera.center_on(from_x + (to_x - from_x) * s, from_y + (to_y - from_y) * s, &mut game.rng);
+				t >= 1.0
+			},
+			WaitStep(ticks) => self.skipping || self.ticks >= ticks,
+			SayStep(name) =>
+			{
+				if first && !self.skipping
+				{
+					// It may have been shown before
+					match game.script.say(name.as_slice())
+					{
+						Some(message) => game.messages.push(message),
+						None if !game.script.has(name.as_slice()) => println!("No message '{}' to say", name),
+						None => ()
+					}
+				}
+				self.skipping || game.messages.is_empty()
+			},
+			SoundStep(name) =>
+			{
+				sfx.play(name.as_slice());
+				true
+			},
+			JoltStep(amount) =>
+			{
+				if !self.skipping
+				{
+					game.camera.jolt(amount);
+				}
+				true
+			},
+			PlayerStep(change) =>
+			{
+				match change
+				{
+					BecomeDemon => game.player.make_demon(),
+					BecomeHuman => game.player.entity_type = Player,
+					FaceLeft => game.player.face_left = true,
+					FaceRight => game.player.face_left = false
+				}
+				true
+			}
+		}
+	}
+}
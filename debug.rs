@@ -0,0 +1,93 @@
+use allegro5::*;
+use allegro_font::*;
+
+#[deriving(Eq, Clone)]
+pub enum DebugMode
+{
+	DebugOff,
+	DebugSupport,
+	DebugLight,
+	DebugDemons,
+	DebugHealth,
+	DebugCollision,
+}
+
+impl DebugMode
+{
+	pub fn next(&self) -> DebugMode
+	{
+		match *self
+		{
+			DebugOff => DebugSupport,
+			DebugSupport => DebugLight,
+			DebugLight => DebugDemons,
+			DebugDemons => DebugHealth,
+			DebugHealth => DebugCollision,
+			DebugCollision => DebugOff,
+		}
+	}
+
+	pub fn get_name(&self) -> &'static str
+	{
+		match *self
+		{
+			DebugOff => "Off",
+			DebugSupport => "Support",
+			DebugLight => "Light",
+			DebugDemons => "Demon policy",
+			DebugHealth => "Tile health",
+			DebugCollision => "Collision",
+		}
+	}
+}
+
+static NUM_SAMPLES: uint = 60;
+
+/* Keeps the last second of update durations */
+pub struct FrameTimer
+{
+	samples: Vec<f64>,
+	next: uint,
+}
+
+impl FrameTimer
+{
+	pub fn new() -> FrameTimer
+	{
+		FrameTimer
+		{
+			samples: Vec::new(),
+			next: 0,
+		}
+	}
+
+	pub fn add(&mut self, start_ns: u64, end_ns: u64)
+	{
+		let ms = (end_ns - start_ns) as f64 / 1e6;
+		if self.samples.len() < NUM_SAMPLES
+		{
+			self.samples.push(ms);
+		}
+		else
+		{
+			*self.samples.get_mut(self.next) = ms;
+		}
+		self.next = (self.next + 1) % NUM_SAMPLES;
+	}
+
+	pub fn draw(&self, core: &Core, font: &Font, mode: DebugMode, x: i32, y: i32)
+	{
+		let mut total = 0.0f64;
+		let mut worst = 0.0f64;
+		for &ms in self.samples.iter()
+		{
+			total += ms;
+			worst = worst.max(ms);
+		}
+		let avg = if self.samples.len() > 0 { total / self.samples.len() as f64 } else { 0.0 };
+
+		let color = core.map_rgb_f(1.0, 1.0, 0.0);
+		core.draw_text(font, color, x as f32, y as f32, AlignLeft, format!("Debug: {}", mode.get_name()));
+		core.draw_text(font, color, x as f32, (y + 10) as f32, AlignLeft, format!("Update: {:.2f} ms avg, {:.2f} ms max", avg, worst));
+	}
+}
@@ -0,0 +1,200 @@
+use std::mem::replace;
+
+use config::Config;
+use input::{Bindings, HelpAction};
+use lang::Strings;
+use message::{Message, MessageType, RadioMessage, JohnMessage, CenteredMessage};
+
+pub static DIALOGUE_DEFS: &'static str = "data/dialogue.cfg";
+
+/* Something that happened in the game that a story beat can wait for */
+#[deriving(Eq, Clone)]
+pub enum Trigger
+{
+	StartTrigger,
+	DepthTrigger(i32),
+	FirstGemTrigger,
+	StoneFoundTrigger,
+	SurfaceTrigger,
+	EndingTrigger,
+	EatenTrigger,
+	CrushedTrigger,
+	AfterTrigger(~str),
+	// Never happens by itself, for messages only said by cutscenes
+	CutsceneTrigger,
+}
+
+impl Trigger
+{
+	/* Parses e.g. `depth 20` or `after intro` */
+	pub fn from_str(s: &str) -> Option<Trigger>
+	{
+		let mut words = s.words();
+		match (words.next(), words.next(), words.next())
+		{
+			(Some("start"), None, None) => Some(StartTrigger),
+			(Some("depth"), Some(n), None) => from_str::<i32>(n).map(|n| DepthTrigger(n)),
+			(Some("first_gem"), None, None) => Some(FirstGemTrigger),
+			(Some("stone_found"), None, None) => Some(StoneFoundTrigger),
+			(Some("surface"), None, None) => Some(SurfaceTrigger),
+			(Some("ending"), None, None) => Some(EndingTrigger),
+			(Some("eaten"), None, None) => Some(EatenTrigger),
+			(Some("crushed"), None, None) => Some(CrushedTrigger),
+			(Some("after"), Some(name), None) => Some(AfterTrigger(name.to_owned())),
+			(Some("cutscene"), None, None) => Some(CutsceneTrigger),
+			_ => None
+		}
+	}
+
+	/* Whether a beat waiting for self goes off when other happens. Depth triggers go off
+	 * at that depth or anything below it. */
+	pub fn matches(&self, other: &Trigger) -> bool
+	{
+		match (self, other)
+		{
+			(&DepthTrigger(need), &DepthTrigger(at)) => at >= need,
+			_ => self == other
+		}
+	}
+}
+
+struct Beat
+{
+	name: ~str,
+	trigger: Trigger,
+	message_type: MessageType,
+	duration: i32,
+	priority: i32,
+	paragraphs: Vec<~str>,
+}
+
+impl Beat
+{
+	fn load(defs: &Config, strings: &Strings, bindings: &Bindings, name: &str) -> Option<Beat>
+	{
+		let trigger_str = defs.get_str(name, "trigger", "");
+		let trigger = match Trigger::from_str(trigger_str.as_slice())
+		{
+			Some(trigger) => trigger,
+			None =>
+			{
+				println!("Unknown trigger '{}' for dialogue {}", trigger_str, name);
+				return None;
+			}
+		};
+		let message_type = match defs.get_str(name, "portrait", "none").as_slice()
+		{
+			"radio_message" => RadioMessage,
+			"john_message" => JohnMessage,
+			"none" => CenteredMessage,
+			portrait =>
+			{
+				println!("Unknown portrait '{}' for dialogue {}", portrait, name);
+				CenteredMessage
+			}
+		};
+
+		let text = defs.get_str(name, "text", "");
+		let help_keys = bindings.describe(HelpAction, "/");
+		let paragraphs = text.split('|').map(|p| strings.fill(p.trim(), [help_keys.as_slice()])).collect();
+
+		Some(Beat
+		{
+			name: name.to_owned(),
+			trigger: trigger,
+			message_type: message_type,
+			duration: defs.get_i32(name, "duration", 240),
+			priority: defs.get_i32(name, "priority", 0),
+			paragraphs: paragraphs,
+		})
+	}
+
+	fn to_message(&self) -> Message
+	{
+		Message::new(self.name.as_slice(), self.message_type, self.duration, self.priority, self.paragraphs.as_slice())
+	}
+}
+
+/* The story, from data/dialogue.cfg with one section per message:
+ *
+ * [found]
+ * trigger = stone_found
+ * portrait = john_message
+ * duration = 240
+ * priority = 1
+ * text = This must be it... Can I escape this hellish place?
+ *
+ * Triggers are start, depth <tiles below the surface>, first_gem, stone_found, surface (when
+ * back up with the stone), ending, eaten, crushed, after <message> to follow on from another
+ * message, and cutscene for messages only said by a cutscene. Portraits are radio_message,
+ * john_message or none for text in the middle of the screen. The text is word wrapped, with |
+ * starting a new paragraph. Every message is shown at most once, the first in the file wins
+ * when several match. Messages with a higher priority interrupt the one showing, the rest wait
+ * their turn. Each paragraph is translated like any other text, so translations go in the
+ * language files. {1} in the text is the key for help.
+ */
+pub struct Script
+{
+	beats: Vec<Beat>,
+	shown: Vec<~str>,
+}
+
+impl Script
+{
+	pub fn load(filename: &str, strings: &Strings, bindings: &Bindings) -> Script
+	{
+		let defs = Config::load(filename).unwrap_or_else(||
+		{
+			println!("Could not load {}, there will be no dialogue", filename);
+			Config::new()
+		});
+		Script
+		{
+			beats: defs.get_sections().iter().filter_map(|name| Beat::load(&defs, strings, bindings, name.as_slice())).collect(),
+			shown: Vec::new(),
+		}
+	}
+
+	/* Rereads the messages, remembering which have been shown */
+	pub fn reload(&mut self, filename: &str, strings: &Strings, bindings: &Bindings)
+	{
+		let shown = replace(&mut self.shown, Vec::new());
+		*self = Script::load(filename, strings, bindings);
+		self.shown = shown;
+	}
+
+	/* The name of the message fire would show, without showing it */
+	pub fn peek(&self, trigger: &Trigger) -> Option<~str>
+	{
+		self.beats.iter().find(|b| b.trigger.matches(trigger) && !self.shown.contains(&b.name)).map(|b| b.name.clone())
+	}
+
+	/* The message waiting for this trigger, if there is one that hasn't been shown yet */
+	pub fn fire(&mut self, trigger: &Trigger) -> Option<Message>
+	{
+		let beat = match self.beats.iter().find(|b| b.trigger.matches(trigger) && !self.shown.contains(&b.name))
+		{
+			Some(beat) => beat,
+			None => return None
+		};
+		self.shown.push(beat.name.clone());
+		Some(beat.to_message())
+	}
+
+	pub fn has(&self, name: &str) -> bool
+	{
+		self.beats.iter().any(|b| b.name.as_slice() == name)
+	}
+
+	/* The message with this name whatever its trigger, if it hasn't been shown yet */
+	pub fn say(&mut self, name: &str) -> Option<Message>
+	{
+		let beat = match self.beats.iter().find(|b| b.name.as_slice() == name && !self.shown.contains(&b.name))
+		{
+			Some(beat) => beat,
+			None => return None
+		};
+		self.shown.push(beat.name.clone());
+		Some(beat.to_message())
+	}
+}
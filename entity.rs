@@ -5,6 +5,7 @@ use std::cmp::{max, min};
 use world::World;
 use camera::Camera;
 use gfx::Gfx;
+use event::{EventQueue, PlayerDied, DeathCause, Crushed};
 
 #[deriving(Eq)]
 pub enum EntityType
@@ -74,7 +75,18 @@ impl Entity
 		self.entity_type = Demon;
 	}
 	
-	pub fn update(&mut self, world: &World)
+	pub fn kill(&mut self, cause: DeathCause, events: &mut EventQueue)
+	{
+		if self.dead
+		{
+			return;
+		}
+		
+		self.dead = true;
+		events.push(PlayerDied{cause: cause});
+	}
+	
+	pub fn update(&mut self, world: &World, events: &mut EventQueue)
 	{
 		if self.dead
 		{
@@ -83,7 +95,7 @@ impl Entity
 		
 		if world.colliding(self.x, self.y, self.w, self.h)
 		{
-			self.dead = true;
+			self.kill(Crushed, events);
 			return;
 		}
 		
@@ -195,7 +207,7 @@ impl Entity
 		
 		if self.dead
 		{
-			gfx.skeleton.draw_no_loop(core, x, y)
+			gfx.skeleton.draw(core, x, y)
 		}
 		else
 		{
@@ -238,26 +250,26 @@ impl Entity
 					{
 						if self.want_left || self.want_up || self.want_down
 						{
-							gfx.player_left.draw_tinted(core, x, y, color);
-							gfx.player_left_hi.draw(core, x, y );
+							gfx.player_left.draw_clip(core, "walk", x, y, color);
+							gfx.player_left_hi.draw_clip(core, "walk", x, y, core.map_rgb_f(1.0, 1.0, 1.0));
 						}
 						else
 						{
-							gfx.player_left.draw_frame(core, 0, x, y, color);
-							gfx.player_left_hi.draw_frame(core, 0, x, y, core.map_rgb_f(1.0, 1.0, 1.0));
+							gfx.player_left.draw_clip(core, "idle", x, y, color);
+							gfx.player_left_hi.draw_clip(core, "idle", x, y, core.map_rgb_f(1.0, 1.0, 1.0));
 						}
 					}
 					else
 					{
 						if self.want_right || self.want_up || self.want_down
 						{
-							gfx.player_right.draw_tinted(core, x, y, color);
-							gfx.player_right_hi.draw(core, x, y );
+							gfx.player_right.draw_clip(core, "walk", x, y, color);
+							gfx.player_right_hi.draw_clip(core, "walk", x, y, core.map_rgb_f(1.0, 1.0, 1.0));
 						}
 						else
 						{
-							gfx.player_right.draw_frame(core, 0, x, y, color);
-							gfx.player_right_hi.draw_frame(core, 0, x, y, core.map_rgb_f(1.0, 1.0, 1.0));
+							gfx.player_right.draw_clip(core, "idle", x, y, color);
+							gfx.player_right_hi.draw_clip(core, "idle", x, y, core.map_rgb_f(1.0, 1.0, 1.0));
 						}
 					}
 				}
@@ -0,0 +1,49 @@
+use std::mem::replace;
+
+use gem::GemColor;
+
+#[deriving(Eq, Clone)]
+pub enum DeathCause
+{
+	Crushed,
+	Eaten,
+}
+
+/* All positions are in world pixels */
+#[deriving(Clone)]
+pub enum GameEvent
+{
+	TileDamaged{x: i32, y: i32},
+	TileMined{x: i32, y: i32},
+	TileCollapsed{x: i32, y: i32},
+	GemCollected{x: i32, y: i32, color: GemColor},
+	DemonKilled{x: i32, y: i32, cause: DeathCause},
+	TorchExtinguished{x: i32, y: i32},
+	PlayerDied{cause: DeathCause},
+}
+
+pub struct EventQueue
+{
+	events: Vec<GameEvent>
+}
+
+impl EventQueue
+{
+	pub fn new() -> EventQueue
+	{
+		EventQueue
+		{
+			events: Vec::new()
+		}
+	}
+	
+	pub fn push(&mut self, event: GameEvent)
+	{
+		self.events.push(event);
+	}
+	
+	pub fn drain(&mut self) -> Vec<GameEvent>
+	{
+		replace(&mut self.events, Vec::new())
+	}
+}
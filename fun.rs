@@ -7,6 +7,7 @@ use world::{World, TILE_SIZE};
 use camera::Camera;
 use util::intersect_rect;
 use gfx::Gfx;
+use event::{EventQueue, DemonKilled, Crushed};
 
 pub struct Demon
 {
@@ -40,7 +41,7 @@ impl Demon
 		}
 	}
 	
-	pub fn update(&mut self, world: &World, player_x: i32, player_y: i32, player_w: i32, player_h: i32) -> bool
+	pub fn update(&mut self, world: &World, player_x: i32, player_y: i32, player_w: i32, player_h: i32, events: &mut EventQueue) -> bool
 	{
 		if self.dead
 		{
@@ -50,6 +51,7 @@ impl Demon
 		if world.colliding(self.x, self.y, self.w, self.h)
 		{
 			self.dead = true;
+			events.push(DemonKilled{x: self.x + self.w / 2, y: self.y + self.h / 2, cause: Crushed});
 			return false;
 		}
 		
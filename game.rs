@@ -0,0 +1,767 @@
+use allegro5::*;
+use allegro_font::*;
+use allegro_primitives::*;
+
+use std::cmp::{min, max};
+
+use world::{World, SURFACE_HEIGHT, TILE_SIZE};
+use camera::{Camera, CameraSettings};
+use entity::*;
+use gem::{Gem, Purple, Phil};
+use fun::Demon;
+use torch::Torch;
+use message::{Message, MessageQueue};
+use gfx::Gfx;
+use sfx::Sfx;
+use event::*;
+use stats::Stats;
+use input::*;
+use particle::ParticleSystem;
+use backdrop::{Backdrop, DayCycle};
+use spatial;
+use ambience::Ambience;
+use dialogue::*;
+use cutscene::{Cutscenes, Cutscene, CUTSCENE_DEFS};
+use lang::Strings;
+
+use rand::{task_rng, Rng, XorShiftRng, SeedableRng};
+
+#[deriving(Eq, Clone)]
+pub enum GameState
+{
+	Playing,
+	Dead,
+	Won,
+	Ending,
+}
+
+pub struct Game
+{
+	pub world: World,
+	pub camera: Camera,
+	pub player: Entity,
+	pub gems: Vec<Gem>,
+	pub demons: Vec<Demon>,
+	pub torches: Vec<Torch>,
+	pub phil: Gem,
+	pub messages: MessageQueue,
+	pub script: Script,
+	pub cutscenes: Cutscenes,
+	pub cutscene: Option<Cutscene>,
+	pub state: GameState,
+	pub events: EventQueue,
+	pub stats: Stats,
+	pub particles: ParticleSystem,
+	pub day: DayCycle,
+	pub backdrop: Backdrop,
+	pub ambience: Ambience,
+	// Everything random once the level is made, so a seeded game always plays out the same
+	pub rng: XorShiftRng,
+
+	pub mine_up: bool,
+	pub mine_down: bool,
+	pub mine_left: bool,
+	pub mine_right: bool,
+	pub place_support: bool,
+	pub place_torch: bool,
+	pub gem_count: i32,
+	// Gems picked up, leaving out Phil
+	gems_found: i32,
+	pub show_help: bool,
+	pub show_map: bool,
+	pub show_log: bool,
+	pub peek_up: bool,
+	pub peek_down: bool,
+}
+
+impl Game
+{
+	pub fn new(gfx: &Gfx, strings: &Strings, bindings: &Bindings, camera_settings: &CameraSettings, view_width: i32, view_height: i32) -> Game
+	{
+		Game::with_rng(gfx, strings, bindings, camera_settings, view_width, view_height, &mut task_rng())
+	}
+
+	/* The level is generated entirely from rng, so a seeded one always gives the same level */
+	pub fn with_rng<R: Rng>(gfx: &Gfx, strings: &Strings, bindings: &Bindings, camera_settings: &CameraSettings, view_width: i32, view_height: i32, rng: &mut R) -> Game
+	{
+		let mut world = World::new(gfx.tiles.clone(), 30, 90, &mut *rng);
+		let camera = Camera::new(camera_settings, view_width, view_height, world.get_pixel_width(), world.get_pixel_height());
+
+		let mut gem_spots: Vec<(bool, (i32, i32))> = Vec::new();
+		let mut demons: Vec<Demon> = Vec::new();
+
+		let phil_loc = world.add_caves(&mut *rng,
+		|(x, y)|
+		{
+			demons.push(Demon::new(x, y))
+		},
+		|rare, loc|
+		{
+			//~ println!("rare: {}, loc: {}", rare, loc);
+			gem_spots.push((rare, loc));
+		});
+
+		let mut script = Script::load(DIALOGUE_DEFS, strings, bindings);
+		let mut messages = MessageQueue::new();
+		script.fire(&StartTrigger).map(|m| messages.push(m));
+		let mut cutscenes = Cutscenes::load(CUTSCENE_DEFS);
+		let cutscene = cutscenes.start(&StartTrigger);
+
+		// XorShift can't be seeded with all zeroes
+		let game_rng: XorShiftRng = SeedableRng::from_seed([rng.gen::<u32>() | 1, rng.gen(), rng.gen(), rng.gen()]);
+
+		let mut gems: Vec<Gem> = Vec::new();
+		for &(rare, (x, y)) in gem_spots.iter()
+		{
+			gems.push(if rare
+			{
+				Gem::with_color(x, y, Purple)
+			}
+			else
+			{
+				Gem::new(x, y, &mut *rng)
+			});
+		}
+
+		Game
+		{
+			world: world,
+			camera: camera,
+			player: Entity::player(20, 20),
+			gems: gems,
+			demons: demons,
+			torches: Vec::new(),
+			phil: Gem::with_color(phil_loc.val0(), phil_loc.val1(), Phil),
+			//~ phil: Gem::with_color(128, 128, Phil),
+			messages: messages,
+			script: script,
+			cutscenes: cutscenes,
+			cutscene: cutscene,
+			state: Playing,
+			events: EventQueue::new(),
+			stats: Stats::new(),
+			particles: ParticleSystem::new(),
+			day: DayCycle::new(),
+			backdrop: Backdrop::new(&mut *rng),
+			ambience: Ambience::new(),
+			rng: game_rng,
+
+			mine_up: false,
+			mine_down: false,
+			mine_left: false,
+			mine_right: false,
+			place_support: false,
+			place_torch: false,
+			gem_count: 20,
+			gems_found: 0,
+			show_help: false,
+			show_map: false,
+			show_log: false,
+			peek_up: false,
+			peek_down: false,
+		}
+	}
+
+	pub fn is_over(&self) -> bool
+	{
+		self.state == Ending || self.state == Dead
+	}
+
+	/* Which music tracks should be playing and how loud: one for the depth or ending, and
+	 * the danger layer on top of it when awake demons are close */
+	pub fn get_music_mix(&self) -> Vec<(&'static str, f32)>
+	{
+		match self.state
+		{
+			Won => vec![("won", 1.0)],
+			Ending => vec![("ending", 1.0)],
+			Dead => vec![],
+			Playing =>
+			{
+				let px = self.player.x + self.player.w / 2;
+				let py = self.player.y + self.player.h / 2;
+				let depth = py / TILE_SIZE - SURFACE_HEIGHT;
+				let base = if depth < 10 { "surface" } else if depth < 45 { "caves" } else { "deep" };
+				
+				let range = (8 * TILE_SIZE) as f32;
+				let danger = self.demons.iter().filter(|d| d.active).fold(0.0f32, |danger, d|
+				{
+					let dx = (d.x + d.w / 2 - px) as f32;
+					let dy = (d.y + d.h / 2 - py) as f32;
+					danger.max(1.0 - (dx * dx + dy * dy).sqrt() / range)
+				});
+				
+				vec![(base, 1.0), ("danger", danger)]
+			}
+		}
+	}
+
+	/* Starts the cutscene and shows the script's message for the trigger, if there are any.
+	 * Returns whether there were. */
+	fn show(&mut self, trigger: Trigger) -> bool
+	{
+		let cutscene = self.start_cutscene(&trigger);
+		// The cutscene says it when its turn comes
+		if cutscene
+		{
+			let said_later = match (self.script.peek(&trigger), self.cutscene.as_ref())
+			{
+				(Some(name), Some(c)) => c.says(name.as_slice()),
+				_ => false
+			};
+			if said_later
+			{
+				return true;
+			}
+		}
+		match self.script.fire(&trigger)
+		{
+			Some(message) =>
+			{
+				self.messages.push(message);
+				true
+			},
+			None => cutscene
+		}
+	}
+
+	/* Only one cutscene plays at a time, the rest of the triggers just show their messages.
+	 * Returns whether one started. */
+	fn start_cutscene(&mut self, trigger: &Trigger) -> bool
+	{
+		if self.cutscene.is_some()
+		{
+			return false;
+		}
+		self.cutscene = self.cutscenes.start(trigger);
+		self.cutscene.is_some()
+	}
+
+	/* For the golden images, which want the game as it is when first started */
+	pub fn disable_cutscenes(&mut self)
+	{
+		self.cutscenes = Cutscenes::new();
+		self.cutscene = None;
+	}
+
+	/* Called once every message has been shown, to move the ending along */
+	fn messages_done(&mut self, sfx: &mut Sfx)
+	{
+		// Cutscenes move the story along themselves
+		if self.cutscene.is_some()
+		{
+			return;
+		}
+
+		if self.state == Won
+		{
+			// The ending cutscene plays out at the surface, see cutscene_done
+			if self.start_cutscene(&EndingTrigger)
+			{
+				return;
+			}
+			self.state = Ending;
+			if !self.show(EndingTrigger)
+			{
+				self.messages_done(sfx);
+			}
+		}
+		else if self.state == Ending
+		{
+			self.player.make_demon();
+			sfx.play("end");
+		}
+	}
+
+	fn cutscene_done(&mut self)
+	{
+		if self.state == Won
+		{
+			self.state = Ending;
+		}
+	}
+
+	pub fn action_down(&mut self, action: Action)
+	{
+		// Cutscenes play out by themselves, the messages can still be skipped and read back
+		if self.cutscene.is_some() && action != SkipMessageAction && action != LogAction
+		{
+			return;
+		}
+
+		match action
+		{
+			// The log takes over climbing while it's open
+			MoveUpAction if self.show_log => self.messages.scroll_log(1),
+			MoveDownAction if self.show_log => self.messages.scroll_log(-1),
+			MoveLeftAction => self.player.want_left = true,
+			MoveRightAction => self.player.want_right = true,
+			MoveUpAction => self.player.want_up = true,
+			MoveDownAction => self.player.want_down = true,
+			DrillLeftAction => self.mine_left = true,
+			DrillRightAction => self.mine_right = true,
+			DrillUpAction => self.mine_up = true,
+			DrillDownAction => self.mine_down = true,
+			PlaceSupportAction => self.place_support = true,
+			PlaceTorchAction => self.place_torch = true,
+			JumpAction => self.player.jump(&self.world),
+			HelpAction => self.show_help = true,
+			MapAction => self.show_map = !self.show_map,
+			// Once there are no messages left to skip, the cutscene is skipped instead
+			SkipMessageAction if self.cutscene.is_some() && self.messages.is_empty() =>
+			{
+				self.cutscene.as_mut().map(|c| c.skip());
+			},
+			SkipMessageAction => self.messages.skip(),
+			LogAction => self.show_log = !self.show_log,
+			PeekUpAction => self.peek_up = true,
+			PeekDownAction => self.peek_down = true,
+			ZoomAction | DebugAction | PauseAction | ScreenshotAction | RecordAction => ()
+		}
+		self.update_drill_direction();
+	}
+
+	pub fn action_up(&mut self, action: Action)
+	{
+		match action
+		{
+			MoveLeftAction => self.player.want_left = false,
+			MoveRightAction => self.player.want_right = false,
+			MoveUpAction => self.player.want_up = false,
+			MoveDownAction => self.player.want_down = false,
+			DrillLeftAction => self.mine_left = false,
+			DrillRightAction => self.mine_right = false,
+			DrillUpAction => self.mine_up = false,
+			DrillDownAction => self.mine_down = false,
+			PlaceSupportAction => self.place_support = false,
+			PlaceTorchAction => self.place_torch = false,
+			HelpAction => self.show_help = false,
+			PeekUpAction => self.peek_up = false,
+			PeekDownAction => self.peek_down = false,
+			JumpAction | MapAction | SkipMessageAction | LogAction | ZoomAction | DebugAction | PauseAction | ScreenshotAction | RecordAction => ()
+		}
+		self.update_drill_direction();
+	}
+
+	pub fn release_actions(&mut self)
+	{
+		for a in ALL_ACTIONS.iter()
+		{
+			self.action_up(*a);
+		}
+	}
+
+	fn update_drill_direction(&mut self)
+	{
+		self.player.drill_direction = match (self.mine_left, self.mine_right, self.mine_up, self.mine_down)
+		{
+			(true, _, _, _) => DrillLeft,
+			(_, true, _, _) => DrillRight,
+			(_, _, true, _) => DrillUp,
+			(_, _, _, true) => DrillDown,
+			_ => DrillNone
+		};
+	}
+
+	pub fn update(&mut self, gfx: &mut Gfx, sfx: &mut Sfx)
+	{
+		gfx.update();
+		
+		// The world waits for cutscenes
+		if self.state == Playing && self.cutscene.is_none()
+		{
+			// Player
+			self.player.update(&self.world, &mut self.events);
+
+			if !self.player.dead
+			{
+				sfx.set_playing("walk", self.player.want_left || self.player.want_right);
+				sfx.set_playing("drill", self.player.drill_direction != DrillNone);
+				
+				if self.player.drill_direction == DrillNone && (self.player.want_left || self.player.want_right)
+				{
+					let sprite = if self.player.face_left { &gfx.player_left } else { &gfx.player_right };
+					if sprite.get_events("walk").iter().any(|e| *e == "step")
+					{
+						self.particles.footstep(&mut self.rng, self.player.x + self.player.w / 2, self.player.y + self.player.h);
+					}
+				}
+			}
+
+			match self.world.get_tile_coords(self.player.x + self.player.w / 2, self.player.y + self.player.h / 2)
+			{
+				Some((_, ty)) => if self.phil.dead && ty <= SURFACE_HEIGHT as uint
+				{
+					self.state = Won;
+					if !self.show(SurfaceTrigger) && self.messages.is_empty()
+					{
+						self.messages_done(sfx);
+					}
+				}
+				else
+				{
+					if !self.show(DepthTrigger(ty as i32 - SURFACE_HEIGHT)) && self.gems_found > 0
+					{
+						self.show(FirstGemTrigger);
+					}
+				},
+				None => ()
+			}
+
+			let (px, py, pw, ph) = (self.player.x, self.player.y, self.player.w, self.player.h);
+
+			// Gems
+			for g in self.gems.mut_iter()
+			{
+				g.update(&self.world, px, py, pw, ph, &mut self.events);
+			}
+			self.gems.retain(|g| !g.dead);
+
+			// Demons
+			for d in self.demons.mut_iter()
+			{
+				if d.update(&self.world, px, py, pw, ph, &mut self.events)
+				{
+					self.player.kill(Eaten, &mut self.events);
+				}
+			}
+			self.demons.retain(|d| !d.dead);
+
+			// Phil
+			self.phil.update(&self.world, px, py, pw, ph, &mut self.events);
+
+			// Torches
+			for t in self.torches.mut_iter()
+			{
+				t.update(&self.world, &mut self.events);
+				if self.rng.gen_weighted_bool(8)
+				{
+					self.particles.embers(&mut self.rng, t.x + t.w / 2, t.y + t.h / 2);
+				}
+			}
+			self.torches.retain(|d| !d.dead);
+
+			// Time of day, relighting only when the change is noticeable
+			self.day.update();
+			let daylight = self.day.get_daylight();
+			if (daylight - self.world.get_daylight()).abs() > 0.05
+			{
+				self.world.set_daylight(daylight);
+			}
+
+			// World
+			let any_falling = self.world.update(self.torches.as_slice(), px, py, pw, ph, &mut self.events);
+
+			// Camera
+			if any_falling
+			{
+				self.camera.jolt(2.0);
+			}
+			// Look where the player is digging, or else where they're going
+			let look_dir = match self.player.drill_direction
+			{
+				DrillLeft => (-1, 0),
+				DrillRight => (1, 0),
+				DrillUp => (0, -1),
+				DrillDown => (0, 1),
+				DrillNone => ((self.player.want_right as i32) - (self.player.want_left as i32), 0)
+			};
+			let peek = (self.peek_down as i32) - (self.peek_up as i32);
+			self.camera.update(px, py, look_dir, peek, &mut self.rng);
+			
+			let collapse_at = if any_falling
+			{
+				self.world.get_nearest_falling(px + pw / 2, py + ph / 2).map(|(x, y)| spatial::locate(&self.world, &self.camera, x, y))
+			}
+			else
+			{
+				None
+			};
+			sfx.set_playing_at("collapse", collapse_at);
+			self.ambience.update(&self.world, &self.camera, self.demons.as_slice(), sfx, &mut self.rng);
+			
+			self.world.update_explored(&self.camera);
+			
+			// Particles
+			self.particles.update(&self.world);
+		}
+		else
+		{
+			sfx.set_playing_at("collapse", None);
+			sfx.set_playing("drill", false);
+			sfx.set_playing("walk", false);
+			self.ambience.fade_out(sfx);
+		}
+
+		// Messages
+		match self.messages.update()
+		{
+			Some(name) =>
+			{
+				if !self.show(AfterTrigger(name)) && self.messages.is_empty()
+				{
+					self.messages_done(sfx);
+				}
+			},
+			None => ()
+		}
+		sfx.set_playing("typing", self.messages.is_typing());
+
+		// Cutscene
+		match self.cutscene.take()
+		{
+			Some(mut cutscene) => if cutscene.update(self, sfx)
+			{
+				self.cutscene = Some(cutscene);
+			}
+			else
+			{
+				self.cutscene_done();
+			},
+			None => ()
+		}
+		//~ println!("{} {}", self.player.x, self.player.y);
+
+		// Player actions
+		if self.state == Playing && !self.player.dead &&
+		   (self.world.on_ground(self.player.x, self.player.y, self.player.w, self.player.h) || self.world.on_support(self.player.x, self.player.y, self.player.w, self.player.h)) &&
+			self.player.vx == 0 && self.player.vy == 0
+		{
+			let spawn_gem = match (self.mine_left, self.mine_right, self.mine_up, self.mine_down)
+			{
+				(true, _, _, _) => self.world.mine(self.player.x, self.player.y, -1,  0, &mut self.events),
+				(_, true, _, _) => self.world.mine(self.player.x, self.player.y,  1,  0, &mut self.events),
+				(_, _, true, _) => self.world.mine(self.player.x, self.player.y,  0, -1, &mut self.events),
+				(_, _, _, true) => self.world.mine(self.player.x, self.player.y,  0,  1, &mut self.events),
+				_ => None
+			};
+
+			match spawn_gem
+			{
+				Some((x, y)) => self.gems.push(Gem::new(x, y, &mut self.rng)),
+				None => ()
+			}
+
+			if self.place_support
+			{
+				if self.gem_count > 1 && self.world.place_support(self.player.x, self.player.y)
+				{
+					self.gem_count -= 2;
+					sfx.play("place");
+				}
+				else
+				{
+					sfx.play("invalid");
+				}
+				self.place_support = false;
+			}
+
+			if self.place_torch
+			{
+				if self.gem_count > 0 && Torch::place_torch(&self.world, &mut self.torches, self.player.x, self.player.y, self.player.w, self.player.h)
+				{
+					self.gem_count -= 1;
+					self.world.need_new_light = true;
+					sfx.play("place");
+				}
+				else
+				{
+					sfx.play("invalid");
+				}
+				self.place_torch = false;
+			}
+		}
+
+		// Events
+		for event in self.events.drain().move_iter()
+		{
+			self.stats.record(&event);
+			match event
+			{
+				TileDamaged{x, y} => self.particles.debris(&mut self.rng, x, y),
+				TileCollapsed{x, y} =>
+				{
+					self.camera.jolt(5.0);
+					self.particles.dust(&mut self.rng, x, y);
+				},
+				GemCollected{x, y, color: Phil} =>
+				{
+					self.particles.sparkles(&mut self.rng, x, y, Phil.get_rgb());
+					self.torches.clear();
+					sfx.play("phil");
+					self.world.need_new_light = true;
+					self.show(StoneFoundTrigger);
+				},
+				GemCollected{x, y, color} =>
+				{
+					self.particles.sparkles(&mut self.rng, x, y, color.get_rgb());
+					self.gem_count += color.get_value();
+					self.gems_found += 1;
+					sfx.play_at("gem", spatial::locate(&self.world, &self.camera, x, y));
+				},
+				DemonKilled{x, y, ..} => sfx.play_at("fun", spatial::locate(&self.world, &self.camera, x, y)),
+				TorchExtinguished{..} => self.world.need_new_light = true,
+				PlayerDied{cause} =>
+				{
+					self.state = Dead;
+					sfx.play("dead");
+					gfx.skeleton.reset();
+					// Nothing else matters now
+					self.messages.clear();
+					self.show(match cause
+					{
+						Eaten => EatenTrigger,
+						Crushed => CrushedTrigger,
+					});
+				},
+				TileMined{..} => ()
+			}
+		}
+	}
+
+	/* Draws the world tiles between min_ty and max_ty, plus everything known to be in them */
+	fn draw_map(&self, core: &Core, prim: &PrimitivesAddon, x: i32, y: i32, scale: i32, min_ty: uint, max_ty: uint)
+	{
+		let w = self.world.get_width() as i32 * scale;
+		let h = (max_ty - min_ty) as i32 * scale;
+		prim.draw_filled_rectangle((x - 1) as f32, (y - 1) as f32, (x + w + 1) as f32, (y + h + 1) as f32, core.map_rgb_f(0.0, 0.0, 0.0));
+		prim.draw_rectangle((x - 1) as f32, (y - 1) as f32, (x + w + 1) as f32, (y + h + 1) as f32, core.map_rgb_f(0.5, 0.5, 0.5), 1.0);
+		
+		self.world.draw_map(core, prim, x, y, scale, 0, min_ty, self.world.get_width(), max_ty);
+		
+		let marker = |px: i32, py: i32, color: Color|
+		{
+			let ty = py / TILE_SIZE;
+			if ty >= min_ty as i32 && ty < max_ty as i32
+			{
+				let mx = (x + px * scale / TILE_SIZE) as f32;
+				let my = (y + py * scale / TILE_SIZE - min_ty as i32 * scale) as f32;
+				prim.draw_filled_rectangle(mx - 1.0, my - 1.0, mx + 1.0, my + 1.0, color);
+			}
+		};
+		
+		for t in self.torches.iter()
+		{
+			marker(t.x + t.w / 2, t.y + t.h / 2, core.map_rgb_f(1.0, 0.6, 0.1));
+		}
+		
+		for g in self.gems.iter()
+		{
+			if self.world.is_explored(g.x + g.w / 2, g.y + g.h / 2)
+			{
+				marker(g.x + g.w / 2, g.y + g.h / 2, g.get_color().get_color(core));
+			}
+		}
+		
+		if !self.phil.dead && self.world.is_explored(self.phil.x + self.phil.w / 2, self.phil.y + self.phil.h / 2)
+		{
+			marker(self.phil.x + self.phil.w / 2, self.phil.y + self.phil.h / 2, self.phil.get_color().get_color(core));
+		}
+		
+		marker(self.player.x + self.player.w / 2, self.player.y + self.player.h / 2, core.map_rgb_f(1.0, 1.0, 1.0));
+	}
+	
+	fn draw_minimap(&self, core: &Core, prim: &PrimitivesAddon, dw: i32)
+	{
+		let scale = 2;
+		let rows = 40;
+		let height = self.world.get_height() as i32;
+		let player_ty = (self.player.y + self.player.h / 2) / TILE_SIZE;
+		let min_ty = min(max(player_ty - rows / 2, 0), max(height - rows, 0));
+		let max_ty = min(min_ty + rows, height);
+		
+		let x = dw - self.world.get_width() as i32 * scale - 6;
+		self.draw_map(core, prim, x, 6, scale, min_ty as uint, max_ty as uint);
+	}
+	
+	fn draw_full_map(&self, core: &Core, prim: &PrimitivesAddon, font: &Font, strings: &Strings, dw: i32, dh: i32)
+	{
+		let width = self.world.get_width() as i32;
+		let height = self.world.get_height() as i32;
+		let scale = max(min((dw - 20) / width, (dh - 30) / height), 1);
+		
+		let x = (dw - width * scale) / 2;
+		let y = (dh - height * scale) / 2 + 5;
+		core.draw_text(font, core.map_rgb_f(1.0, 1.0, 1.0), (dw / 2) as f32, (y - 14) as f32, AlignCentre, strings.tr("Map"));
+		self.draw_map(core, prim, x, y, scale, 0, height as uint);
+	}
+
+	pub fn draw(&self, core: &Core, prim: &PrimitivesAddon, font: &Font, strings: &Strings, gfx: &Gfx, bindings: &Bindings, dw: i32, dh: i32)
+	{
+		// The world is zoomed, the HUD on top of it isn't
+		self.camera.use_transform(core);
+		if !self.is_over()
+		{
+			self.backdrop.draw(core, prim, &self.day, &self.camera);
+		}
+
+		// Everything up to the particles comes from the atlas, so it can be batched
+		core.hold_bitmap_drawing(true);
+		if !self.is_over()
+		{
+			self.world.draw(core, font, &self.camera);
+
+			for t in self.torches.iter()
+			{
+				t.draw(gfx, core, &self.camera);
+			}
+		}
+
+		self.player.draw(gfx, core, &self.world, &self.camera);
+
+		if !self.is_over()
+		{
+			for d in self.demons.iter()
+			{
+				d.draw(gfx, core, &self.world, &self.camera);
+			}
+
+			for g in self.gems.iter()
+			{
+				g.draw(gfx, core, &self.camera);
+			}
+
+			self.phil.draw(gfx, core, &self.camera);
+		}
+		core.hold_bitmap_drawing(false);
+
+		if !self.is_over()
+		{
+			self.particles.draw(core, prim, &self.world, &self.camera);
+		}
+		self.camera.reset_transform(core);
+
+		if !self.is_over()
+		{
+			gfx.ui_gem.draw(core, 10, 10);
+			core.draw_text(font, core.map_rgb_f(1.0, 1.0, 1.0), 42.0, 15.0, AlignLeft, format!("x{}", self.gem_count));
+
+			if self.show_map
+			{
+				self.draw_full_map(core, prim, font, strings, dw, dh);
+			}
+			else
+			{
+				self.draw_minimap(core, prim, dw);
+			}
+
+			if self.show_help
+			{
+				Message::draw_help(dw, dh, core, font, strings, bindings);
+			}
+		}
+		else
+		{
+			self.stats.draw(core, font, strings, dw, dh);
+		}
+
+		if self.show_log
+		{
+			self.messages.draw_log(core, prim, font, strings, dw, dh);
+		}
+		else
+		{
+			self.messages.draw(gfx, dw, dh, core, font);
+		}
+	}
+}
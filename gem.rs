@@ -1,7 +1,7 @@
 use allegro5::*;
 
 use rand::distributions::{Weighted, WeightedChoice, IndependentSample};
-use rand::task_rng;
+use rand::Rng;
 
 //~ use std::cmp::{max, min};
 
@@ -9,6 +9,7 @@ use world::World;
 use camera::Camera;
 use util::intersect_rect;
 use gfx::Gfx;
+use event::{EventQueue, GemCollected};
 
 #[deriving(Eq, Clone)]
 pub enum GemColor
@@ -23,7 +24,7 @@ pub enum GemColor
 
 impl GemColor
 {
-	fn get_value(&self) -> i32
+	pub fn get_value(&self) -> i32
 	{
 		match *self
 		{
@@ -36,18 +37,24 @@ impl GemColor
 		}
 	}
 
-	fn get_color(&self, core: &Core) -> Color
+	pub fn get_rgb(&self) -> (f32, f32, f32)
 	{
 		match *self
 		{
-			Red => core.map_rgb_f(1.0, 0.0, 0.0),
-			Green => core.map_rgb_f(0.0, 1.0, 0.0),
-			Blue => core.map_rgb_f(0.0, 0.0, 1.0),
-			Yellow => core.map_rgb_f(1.0, 1.0, 0.0),
-			Purple => core.map_rgb_f(0.7, 0.0, 0.7),
-			Phil => core.map_rgb(169, 240, 210),
+			Red => (1.0, 0.0, 0.0),
+			Green => (0.0, 1.0, 0.0),
+			Blue => (0.0, 0.0, 1.0),
+			Yellow => (1.0, 1.0, 0.0),
+			Purple => (0.7, 0.0, 0.7),
+			Phil => (169.0 / 255.0, 240.0 / 255.0, 210.0 / 255.0),
 		}
 	}
+
+	pub fn get_color(&self, core: &Core) -> Color
+	{
+		let (r, g, b) = self.get_rgb();
+		core.map_rgb_f(r, g, b)
+	}
 }
 
 pub struct Gem
@@ -64,7 +71,7 @@ pub struct Gem
 
 impl Gem
 {
-	pub fn new(x: i32, y: i32) -> Gem
+	pub fn new<R: Rng>(x: i32, y: i32, rng: &mut R) -> Gem
 	{
 		let wc = WeightedChoice::new(
 		vec![Weighted { weight: 100, item: Red },
@@ -82,7 +89,7 @@ impl Gem
 			w: 8,
 			h: 8,
 			dead: false,
-			color: wc.ind_sample(&mut task_rng())
+			color: wc.ind_sample(rng)
 		}
 	}
 
@@ -100,17 +107,17 @@ impl Gem
 		}
 	}
 	
-	pub fn update(&mut self, world: &World, player_x: i32, player_y: i32, player_w: i32, player_h: i32) -> i32
+	pub fn update(&mut self, world: &World, player_x: i32, player_y: i32, player_w: i32, player_h: i32, events: &mut EventQueue)
 	{
 		if self.dead
 		{
-			return 0;
+			return;
 		}
 		
 		if world.colliding(self.x, self.y, self.w, self.h)
 		{
 			self.dead = true;
-			return 0;
+			return;
 		}
 		
 		self.vy = if world.on_ground(self.x, self.y, self.w, self.h) && self.vy > 0 || world.on_support(self.x, self.y, self.w, self.h)
@@ -129,14 +136,15 @@ impl Gem
 		if intersect_rect(self.x, self.y, self.w, self.h, player_x, player_y, player_w, player_h)
 		{
 			self.dead = true;
-			self.color.get_value()
-		}
-		else
-		{
-			0
+			events.push(GemCollected{x: self.x + self.w / 2, y: self.y + self.h / 2, color: self.color});
 		}
 	}
 
+	pub fn get_color(&self) -> GemColor
+	{
+		self.color
+	}
+
 	pub fn draw(&self, gfx: &Gfx, core: &Core, camera: &Camera)
 	{
 		if self.dead
@@ -1,5 +1,9 @@
 use allegro5::*;
 use sprite::Sprite;
+use config::Config;
+use atlas::Atlas;
+
+static SPRITE_DEFS: &'static str = "data/sprites.cfg";
 
 pub struct Gfx
 {
@@ -26,40 +30,110 @@ pub struct Gfx
 	pub skeleton: Sprite,
 	pub radio_message: Sprite,
 	pub john_message: Sprite,
+	pub tiles: Sprite,
 }
 
 impl Gfx
 {
 	pub fn new(core: &Core) -> Gfx
 	{
+		let defs = Config::load(SPRITE_DEFS).expect(format!("Could not load {}", SPRITE_DEFS));
+		let filenames: Vec<~str> = defs.get_sections().iter().map(|name| Sprite::get_filename(&defs, name.as_slice())).collect();
+		let atlas = Atlas::build(core, filenames.as_slice());
 		Gfx
 		{
-			player_left: Sprite::new(core, "data/player_left.png", 24, 24),
-			player_right: Sprite::new(core, "data/player_right.png", 24, 24),
-			drill_left: Sprite::new(core, "data/drill_left.png", 24, 24),
-			drill_right: Sprite::new(core, "data/drill_right.png", 24, 24),
+			player_left: Sprite::load(core, &defs, "player_left", Some(&atlas)),
+			player_right: Sprite::load(core, &defs, "player_right", Some(&atlas)),
+			drill_left: Sprite::load(core, &defs, "drill_left", Some(&atlas)),
+			drill_right: Sprite::load(core, &defs, "drill_right", Some(&atlas)),
 			
-			drill_up: Sprite::new(core, "data/drill_up.png", 24, 24),
-			drill_down: Sprite::new(core, "data/drill_down.png", 24, 24),
+			drill_up: Sprite::load(core, &defs, "drill_up", Some(&atlas)),
+			drill_down: Sprite::load(core, &defs, "drill_down", Some(&atlas)),
 			
-			player_left_hi: Sprite::new(core, "data/player_left_hi.png", 24, 24),
-			player_right_hi: Sprite::new(core, "data/player_right_hi.png", 24, 24),
-			drill_left_hi: Sprite::new(core, "data/drill_left_hi.png", 24, 24),
-			drill_right_hi: Sprite::new(core, "data/drill_right_hi.png", 24, 24),
+			player_left_hi: Sprite::load(core, &defs, "player_left_hi", Some(&atlas)),
+			player_right_hi: Sprite::load(core, &defs, "player_right_hi", Some(&atlas)),
+			drill_left_hi: Sprite::load(core, &defs, "drill_left_hi", Some(&atlas)),
+			drill_right_hi: Sprite::load(core, &defs, "drill_right_hi", Some(&atlas)),
 			
-			drill_up_hi: Sprite::new(core, "data/drill_up_hi.png", 24, 24),
-			drill_down_hi: Sprite::new(core, "data/drill_down_hi.png", 24, 24),
+			drill_up_hi: Sprite::load(core, &defs, "drill_up_hi", Some(&atlas)),
+			drill_down_hi: Sprite::load(core, &defs, "drill_down_hi", Some(&atlas)),
 			
-			gem: Sprite::new(core, "data/gem.png", 16, 16),
-			gem_hi: Sprite::new(core, "data/gem_hi.png", 16, 16),
-			fun: Sprite::new(core, "data/fun.png", 24, 24),
-			fun_hi: Sprite::new(core, "data/fun_hi.png", 24, 24),
-			torch: Sprite::new(core, "data/torch.png", 16, 16),
-			ui_gem: Sprite::new(core, "data/ui_gem.png", 32, 32),
-			skeleton: Sprite::new(core, "data/skeleton.png", 24, 24),
+			gem: Sprite::load(core, &defs, "gem", Some(&atlas)),
+			gem_hi: Sprite::load(core, &defs, "gem_hi", Some(&atlas)),
+			fun: Sprite::load(core, &defs, "fun", Some(&atlas)),
+			fun_hi: Sprite::load(core, &defs, "fun_hi", Some(&atlas)),
+			torch: Sprite::load(core, &defs, "torch", Some(&atlas)),
+			ui_gem: Sprite::load(core, &defs, "ui_gem", Some(&atlas)),
+			skeleton: Sprite::load(core, &defs, "skeleton", Some(&atlas)),
 			
-			radio_message: Sprite::new(core, "data/radio_message.png", 350, 60),
-			john_message: Sprite::new(core, "data/john_message.png", 350, 60),
+			radio_message: Sprite::load(core, &defs, "radio_message", Some(&atlas)),
+			john_message: Sprite::load(core, &defs, "john_message", Some(&atlas)),
+			tiles: Sprite::load(core, &defs, "tiles", Some(&atlas)),
 		}
 	}
+
+	fn for_each_sprite(&mut self, f: |&'static str, &mut Sprite|)
+	{
+		f("player_left", &mut self.player_left);
+		f("player_right", &mut self.player_right);
+		f("drill_left", &mut self.drill_left);
+		f("drill_right", &mut self.drill_right);
+		f("drill_up", &mut self.drill_up);
+		f("drill_down", &mut self.drill_down);
+		f("player_left_hi", &mut self.player_left_hi);
+		f("player_right_hi", &mut self.player_right_hi);
+		f("drill_left_hi", &mut self.drill_left_hi);
+		f("drill_right_hi", &mut self.drill_right_hi);
+		f("drill_up_hi", &mut self.drill_up_hi);
+		f("drill_down_hi", &mut self.drill_down_hi);
+		f("gem", &mut self.gem);
+		f("gem_hi", &mut self.gem_hi);
+		f("fun", &mut self.fun);
+		f("fun_hi", &mut self.fun_hi);
+		f("torch", &mut self.torch);
+		f("ui_gem", &mut self.ui_gem);
+		f("skeleton", &mut self.skeleton);
+		f("radio_message", &mut self.radio_message);
+		f("john_message", &mut self.john_message);
+		f("tiles", &mut self.tiles);
+	}
+
+	/* Advances every animation by one simulation tick */
+	pub fn update(&mut self)
+	{
+		self.for_each_sprite(|_, sprite| sprite.update());
+	}
+
+	/* Puts every animation back at its start */
+	pub fn reset(&mut self)
+	{
+		self.for_each_sprite(|_, sprite| sprite.reset());
+	}
+
+	/* Reloads the sprites using the changed file, or all of them if it was the definitions.
+	 * Sprites that fail to load keep their old version. */
+	pub fn reload(&mut self, core: &Core, filename: &str)
+	{
+		let defs = match Config::load(SPRITE_DEFS)
+		{
+			Some(defs) => defs,
+			None => return
+		};
+		let all = filename == SPRITE_DEFS;
+		self.for_each_sprite(|name, sprite|
+		{
+			if all || sprite.filename.as_slice() == filename
+			{
+				match Sprite::try_load(core, &defs, name, None)
+				{
+					Some(mut new_sprite) =>
+					{
+						new_sprite.ticks = sprite.ticks;
+						*sprite = new_sprite;
+					},
+					None => println!("Could not reload sprite {}", name)
+				}
+			}
+		});
+	}
 }
@@ -0,0 +1,208 @@
+use allegro5::*;
+use allegro_font::*;
+use allegro_primitives::*;
+
+use std::io::fs::readdir;
+use rand::{XorShiftRng, SeedableRng};
+
+use game::Game;
+use camera::CameraSettings;
+use gfx::Gfx;
+use sfx::Sfx;
+use config::Config;
+use input::{Action, Bindings};
+use lang::Strings;
+
+/* A game state to render, described by a file like:
+ *
+ * [scenario]
+ * seed = 7
+ * ticks = 120
+ * view_width = 400
+ * view_height = 300
+ * hold = move_right, drill_down
+ * tolerance = 0
+ * max_differences = 0
+ *
+ * The level comes from the seed, then the held actions are applied for the given number of ticks.
+ * Everything random comes from the seed too, so by default the image has to match exactly.
+ */
+pub struct Scenario
+{
+	seed: u32,
+	ticks: i32,
+	view_width: i32,
+	view_height: i32,
+	held: Vec<Action>,
+	tolerance: f32,
+	max_differences: uint,
+}
+
+impl Scenario
+{
+	pub fn load(filename: &str) -> Option<Scenario>
+	{
+		Config::load(filename).map(|config|
+		{
+			let mut held = Vec::new();
+			for name in config.get_str("scenario", "hold", "").split(',').map(|n| n.trim()).filter(|n| n.len() > 0)
+			{
+				match Action::from_name(name)
+				{
+					Some(action) => held.push(action),
+					None => println!("Unknown action '{}' in {}", name, filename)
+				}
+			}
+
+			Scenario
+			{
+				seed: config.get_i32("scenario", "seed", 1) as u32,
+				ticks: config.get_i32("scenario", "ticks", 0),
+				view_width: config.get_i32("scenario", "view_width", 400),
+				view_height: config.get_i32("scenario", "view_height", 300),
+				held: held,
+				tolerance: config.get_f32("scenario", "tolerance", 0.0),
+				max_differences: config.get_i32("scenario", "max_differences", 0) as uint,
+			}
+		})
+	}
+
+	pub fn run(&self, gfx: &mut Gfx, sfx: &mut Sfx, strings: &Strings, bindings: &Bindings) -> Game
+	{
+		// XorShift can't be seeded with all zeroes, so the seed only goes in the first word
+		let mut rng: XorShiftRng = SeedableRng::from_seed([self.seed, 0x9e3779b9, 0x85ebca6b, 0xc2b2ae35]);
+		// The classic camera and no cutscenes, so the images don't depend on the settings
+		let mut game = Game::with_rng(gfx, strings, bindings, &CameraSettings::new(), self.view_width, self.view_height, &mut rng);
+		game.disable_cutscenes();
+		// Scenarios share the sprites, so they'd otherwise start where the last one left off
+		gfx.reset();
+		for action in self.held.iter()
+		{
+			game.action_down(*action);
+		}
+		for _ in range(0, self.ticks)
+		{
+			game.update(gfx, sfx);
+		}
+		game
+	}
+}
+
+/* Draws the game, HUD and messages included, into a fresh bitmap */
+pub fn render_frame(core: &Core, prim: &PrimitivesAddon, font: &Font, strings: &Strings, gfx: &Gfx, game: &Game, bindings: &Bindings, width: i32, height: i32) -> Bitmap
+{
+	let bmp = core.create_bitmap(width, height).expect("Could not create the render bitmap");
+	core.set_target_bitmap(&bmp);
+	core.clear_to_color(core.map_rgb_f(0.0, 0.0, 0.0));
+	game.draw(core, prim, font, strings, gfx, bindings, width, height);
+	bmp
+}
+
+/* Number of pixels where some channel differs by more than tolerance, or None if the sizes differ */
+pub fn count_differences(a: &Bitmap, b: &Bitmap, tolerance: f32) -> Option<uint>
+{
+	if a.get_width() != b.get_width() || a.get_height() != b.get_height()
+	{
+		return None;
+	}
+
+	let mut count = 0;
+	for y in range(0, a.get_height())
+	{
+		for x in range(0, a.get_width())
+		{
+			let (r1, g1, b1, a1) = a.get_pixel(x, y).unmap_rgba_f();
+			let (r2, g2, b2, a2) = b.get_pixel(x, y).unmap_rgba_f();
+			if (r1 - r2).abs() > tolerance || (g1 - g2).abs() > tolerance ||
+			   (b1 - b2).abs() > tolerance || (a1 - a2).abs() > tolerance
+			{
+				count += 1;
+			}
+		}
+	}
+	Some(count)
+}
+
+/* Renders every scenario in dir and compares it against the golden image next to it, e.g.
+ * cave.cfg against cave.png. Mismatches are written out as cave.out.png. With bless set the
+ * render becomes the new golden image instead, otherwise a missing one is a failure. Returns
+ * whether everything matched. */
+pub fn run_golden(core: &Core, prim: &PrimitivesAddon, font: &Font, strings: &Strings, gfx: &mut Gfx, sfx: &mut Sfx, dir: &str, bless: bool) -> bool
+{
+	let mut paths = match readdir(&Path::new(dir))
+	{
+		Ok(paths) => paths,
+		Err(e) =>
+		{
+			println!("Failed to read {}: {}", dir, e);
+			return false;
+		}
+	};
+	paths.retain(|p| p.extension_str() == Some("cfg"));
+	paths.sort_by(|a, b| a.as_vec().cmp(&b.as_vec()));
+
+	let bindings = Bindings::new();
+	let mut failures = 0;
+	for path in paths.iter()
+	{
+		let name = path.filestem_str().unwrap_or("?");
+		let scenario = match Scenario::load(path.as_str().unwrap_or(""))
+		{
+			Some(scenario) => scenario,
+			None =>
+			{
+				println!("FAIL {}: could not load the scenario", name);
+				failures += 1;
+				continue;
+			}
+		};
+
+		let game = scenario.run(gfx, sfx, strings, &bindings);
+		let frame = render_frame(core, prim, font, strings, gfx, &game, &bindings, scenario.view_width, scenario.view_height);
+
+		let golden_path = path.with_extension("png");
+		let golden_name = golden_path.as_str().unwrap_or("");
+		if bless
+		{
+			if core.save_bitmap(golden_name, &frame)
+			{
+				println!("BLESS {}", name);
+			}
+			else
+			{
+				println!("FAIL {}: could not write {}", name, golden_name);
+				failures += 1;
+			}
+			continue;
+		}
+
+		match core.load_bitmap(golden_name)
+		{
+			None =>
+			{
+				let out_path = path.with_extension("out.png");
+				core.save_bitmap(out_path.as_str().unwrap_or(""), &frame);
+				println!("FAIL {}: no golden image {}, see {} or run with --bless", name, golden_name, out_path.display());
+				failures += 1;
+			},
+			Some(golden) => match count_differences(&frame, &golden, scenario.tolerance)
+			{
+				Some(n) if n <= scenario.max_differences => println!("PASS {}", name),
+				diff =>
+				{
+					let out_path = path.with_extension("out.png");
+					core.save_bitmap(out_path.as_str().unwrap_or(""), &frame);
+					match diff
+					{
+						Some(n) => println!("FAIL {}: {} pixels differ, see {}", name, n, out_path.display()),
+						None => println!("FAIL {}: size differs, see {}", name, out_path.display()),
+					}
+					failures += 1;
+				}
+			}
+		}
+	}
+
+	println!("{} of {} scenarios passed", paths.len() - failures, paths.len());
+	failures == 0
+}
@@ -0,0 +1,733 @@
+use allegro5::*;
+
+use std::ascii::StrAsciiExt;
+use std::mem::replace;
+
+use config::Config;
+use menu::*;
+
+#[deriving(Eq, Clone)]
+pub enum Action
+{
+	MoveLeftAction,
+	MoveRightAction,
+	MoveUpAction,
+	MoveDownAction,
+	DrillLeftAction,
+	DrillRightAction,
+	DrillUpAction,
+	DrillDownAction,
+	JumpAction,
+	PlaceSupportAction,
+	PlaceTorchAction,
+	HelpAction,
+	MapAction,
+	SkipMessageAction,
+	LogAction,
+	PeekUpAction,
+	PeekDownAction,
+	ZoomAction,
+	DebugAction,
+	PauseAction,
+	ScreenshotAction,
+	RecordAction,
+}
+
+pub static ALL_ACTIONS: &'static [Action] = &[
+	MoveLeftAction,
+	MoveRightAction,
+	MoveUpAction,
+	MoveDownAction,
+	DrillLeftAction,
+	DrillRightAction,
+	DrillUpAction,
+	DrillDownAction,
+	JumpAction,
+	PlaceSupportAction,
+	PlaceTorchAction,
+	HelpAction,
+	MapAction,
+	SkipMessageAction,
+	LogAction,
+	PeekUpAction,
+	PeekDownAction,
+	ZoomAction,
+	DebugAction,
+	PauseAction,
+	ScreenshotAction,
+	RecordAction,
+];
+
+impl Action
+{
+	/* Name used in the config file */
+	pub fn get_name(&self) -> &'static str
+	{
+		match *self
+		{
+			MoveLeftAction => "move_left",
+			MoveRightAction => "move_right",
+			MoveUpAction => "move_up",
+			MoveDownAction => "move_down",
+			DrillLeftAction => "drill_left",
+			DrillRightAction => "drill_right",
+			DrillUpAction => "drill_up",
+			DrillDownAction => "drill_down",
+			JumpAction => "jump",
+			PlaceSupportAction => "place_support",
+			PlaceTorchAction => "place_torch",
+			HelpAction => "help",
+			MapAction => "map",
+			SkipMessageAction => "skip_message",
+			LogAction => "message_log",
+			PeekUpAction => "peek_up",
+			PeekDownAction => "peek_down",
+			ZoomAction => "zoom",
+			DebugAction => "debug",
+			PauseAction => "pause",
+			ScreenshotAction => "screenshot",
+			RecordAction => "record",
+		}
+	}
+
+	pub fn from_name(name: &str) -> Option<Action>
+	{
+		ALL_ACTIONS.iter().find(|a| a.get_name() == name).map(|a| *a)
+	}
+
+	pub fn get_description(&self) -> &'static str
+	{
+		match *self
+		{
+			MoveLeftAction => "Move left",
+			MoveRightAction => "Move right",
+			MoveUpAction => "Climb up",
+			MoveDownAction => "Climb down",
+			DrillLeftAction => "Dig left",
+			DrillRightAction => "Dig right",
+			DrillUpAction => "Dig up",
+			DrillDownAction => "Dig down",
+			JumpAction => "Jump",
+			PlaceSupportAction => "Place support",
+			PlaceTorchAction => "Place torch",
+			HelpAction => "Help",
+			MapAction => "Map",
+			SkipMessageAction => "Skip message",
+			LogAction => "Message log",
+			PeekUpAction => "Look up",
+			PeekDownAction => "Look down",
+			ZoomAction => "Zoom",
+			DebugAction => "Debug overlay",
+			PauseAction => "Pause",
+			ScreenshotAction => "Screenshot",
+			RecordAction => "Start/stop recording",
+		}
+	}
+
+	fn get_default_keys(&self) -> Vec<key::KeyCode>
+	{
+		match *self
+		{
+			MoveLeftAction => vec![key::Left],
+			MoveRightAction => vec![key::Right],
+			MoveUpAction => vec![key::Up],
+			MoveDownAction => vec![key::Down],
+			DrillLeftAction => vec![key::A],
+			DrillRightAction => vec![key::D],
+			DrillUpAction => vec![key::W],
+			DrillDownAction => vec![key::S],
+			JumpAction => vec![key::Space],
+			PlaceSupportAction => vec![key::R],
+			PlaceTorchAction => vec![key::T],
+			HelpAction => vec![key::F1],
+			MapAction => vec![key::M],
+			SkipMessageAction => vec![key::Enter],
+			LogAction => vec![key::L],
+			PeekUpAction => vec![key::PgUp],
+			PeekDownAction => vec![key::PgDn],
+			ZoomAction => vec![key::Z],
+			DebugAction => vec![key::F3],
+			PauseAction => vec![key::Escape],
+			ScreenshotAction => vec![key::F12],
+			RecordAction => vec![key::F11],
+		}
+	}
+}
+
+static KEY_NAMES: &'static [(&'static str, key::KeyCode)] = &[
+	("A", key::A),
+	("B", key::B),
+	("C", key::C),
+	("D", key::D),
+	("E", key::E),
+	("F", key::F),
+	("G", key::G),
+	("H", key::H),
+	("I", key::I),
+	("J", key::J),
+	("K", key::K),
+	("L", key::L),
+	("M", key::M),
+	("N", key::N),
+	("O", key::O),
+	("P", key::P),
+	("Q", key::Q),
+	("R", key::R),
+	("S", key::S),
+	("T", key::T),
+	("U", key::U),
+	("V", key::V),
+	("W", key::W),
+	("X", key::X),
+	("Y", key::Y),
+	("Z", key::Z),
+	("0", key::_0),
+	("1", key::_1),
+	("2", key::_2),
+	("3", key::_3),
+	("4", key::_4),
+	("5", key::_5),
+	("6", key::_6),
+	("7", key::_7),
+	("8", key::_8),
+	("9", key::_9),
+	("F1", key::F1),
+	("F2", key::F2),
+	("F3", key::F3),
+	("F4", key::F4),
+	("F5", key::F5),
+	("F6", key::F6),
+	("F7", key::F7),
+	("F8", key::F8),
+	("F9", key::F9),
+	("F10", key::F10),
+	("F11", key::F11),
+	("F12", key::F12),
+	("Left", key::Left),
+	("Right", key::Right),
+	("Up", key::Up),
+	("Down", key::Down),
+	("Space", key::Space),
+	("Enter", key::Enter),
+	("Escape", key::Escape),
+	("Tab", key::Tab),
+	("Backspace", key::Backspace),
+	("LShift", key::LShift),
+	("RShift", key::RShift),
+	("LCtrl", key::LCtrl),
+	("RCtrl", key::RCtrl),
+	("Alt", key::Alt),
+	("Insert", key::Insert),
+	("Delete", key::Delete),
+	("Home", key::Home),
+	("End", key::End),
+	("PgUp", key::PgUp),
+	("PgDn", key::PgDn),
+	("Pad0", key::Pad0),
+	("Pad1", key::Pad1),
+	("Pad2", key::Pad2),
+	("Pad3", key::Pad3),
+	("Pad4", key::Pad4),
+	("Pad5", key::Pad5),
+	("Pad6", key::Pad6),
+	("Pad7", key::Pad7),
+	("Pad8", key::Pad8),
+	("Pad9", key::Pad9),
+];
+
+pub fn key_name(k: key::KeyCode) -> &'static str
+{
+	for &(name, k2) in KEY_NAMES.iter()
+	{
+		if k2 == k
+		{
+			return name;
+		}
+	}
+	"?"
+}
+
+pub fn key_from_name(name: &str) -> Option<key::KeyCode>
+{
+	for &(name2, k) in KEY_NAMES.iter()
+	{
+		if name2.eq_ignore_ascii_case(name)
+		{
+			return Some(k);
+		}
+	}
+	None
+}
+
+pub struct Bindings
+{
+	keys: Vec<(Action, Vec<key::KeyCode>)>,
+}
+
+impl Bindings
+{
+	pub fn new() -> Bindings
+	{
+		Bindings
+		{
+			keys: ALL_ACTIONS.iter().map(|a| (*a, a.get_default_keys())).collect()
+		}
+	}
+
+	/* Reads the [keys] section, e.g. `jump = Space, Up`. Missing entries keep their defaults, empty ones are unbound */
+	pub fn load(config: &Config) -> Bindings
+	{
+		let mut bindings = Bindings::new();
+		for &(action, ref mut keys) in bindings.keys.mut_iter()
+		{
+			match config.get("keys", action.get_name())
+			{
+				Some(value) =>
+				{
+					let mut new_keys = Vec::new();
+					for name in value.split(',').map(|n| n.trim()).filter(|n| n.len() > 0)
+					{
+						match key_from_name(name)
+						{
+							Some(k) => new_keys.push(k),
+							None => println!("Unknown key '{}' for {}", name, action.get_name())
+						}
+					}
+					*keys = new_keys;
+				},
+				None => ()
+			}
+		}
+		bindings
+	}
+
+	pub fn save(&self, config: &mut Config)
+	{
+		for &(action, _) in self.keys.iter()
+		{
+			config.set("keys", action.get_name(), self.describe(action, ", ").as_slice());
+		}
+	}
+
+	pub fn get_actions(&self, k: key::KeyCode) -> Vec<Action>
+	{
+		self.keys.iter().filter(|&&(_, ref keys)| keys.contains(&k)).map(|&(a, _)| a).collect()
+	}
+
+	pub fn get_keys<'l>(&'l self, action: Action) -> &'l [key::KeyCode]
+	{
+		for &(a, ref keys) in self.keys.iter()
+		{
+			if a == action
+			{
+				return keys.as_slice();
+			}
+		}
+		unreachable!()
+	}
+
+	/* Binds the action to just this key, removing the key from any other action. An action
+	 * left with no keys gets the ones this action had, so nothing ends up unbound. */
+	pub fn set_key(&mut self, action: Action, k: key::KeyCode)
+	{
+		let old_keys: Vec<key::KeyCode> = self.get_keys(action).iter().map(|k2| *k2).filter(|k2| *k2 != k).collect();
+		for &(a, ref mut keys) in self.keys.mut_iter()
+		{
+			if a == action
+			{
+				*keys = vec![k];
+			}
+			else if keys.contains(&k)
+			{
+				keys.retain(|k2| *k2 != k);
+				if keys.is_empty()
+				{
+					*keys = old_keys.clone();
+				}
+			}
+		}
+	}
+
+	pub fn describe(&self, action: Action, sep: &str) -> ~str
+	{
+		let names: Vec<&str> = self.get_keys(action).iter().map(|k| key_name(*k)).collect();
+		names.as_slice().connect(sep)
+	}
+}
+
+#[deriving(Eq, Clone)]
+pub enum InputEvent
+{
+	ActionPressed(Action),
+	ActionReleased(Action),
+	MenuPressed(MenuAction),
+	KeyCaptured(key::KeyCode),
+}
+
+struct JoystickMap
+{
+	move_stick: i32,
+	drill_stick: i32,
+	dpad_stick: i32,
+	dead_zone: f32,
+	buttons: Vec<(i32, Action)>,
+}
+
+impl JoystickMap
+{
+	/* Defaults match a typical twin-stick pad: A jumps, B places supports, X places torches */
+	fn load(config: &Config) -> JoystickMap
+	{
+		let mut buttons = Vec::new();
+		for &(action, default) in [(JumpAction, 0), (PlaceSupportAction, 1), (PlaceTorchAction, 2),
+		                           (HelpAction, 3), (SkipMessageAction, 4), (LogAction, 5), (MapAction, 6), (PauseAction, 7)].iter()
+		{
+			let button = config.get_i32("joystick", action.get_name(), default);
+			if button >= 0
+			{
+				buttons.push((button, action));
+			}
+		}
+		
+		JoystickMap
+		{
+			move_stick: config.get_i32("joystick", "move_stick", 0),
+			drill_stick: config.get_i32("joystick", "drill_stick", 1),
+			dpad_stick: config.get_i32("joystick", "dpad_stick", 2),
+			dead_zone: config.get_f32("joystick", "dead_zone", 0.5),
+			buttons: buttons,
+		}
+	}
+	
+	fn save(&self, config: &mut Config)
+	{
+		config.set_i32("joystick", "move_stick", self.move_stick);
+		config.set_i32("joystick", "drill_stick", self.drill_stick);
+		config.set_i32("joystick", "dpad_stick", self.dpad_stick);
+		config.set_f32("joystick", "dead_zone", self.dead_zone);
+		for &(button, action) in self.buttons.iter()
+		{
+			config.set_i32("joystick", action.get_name(), button);
+		}
+	}
+	
+	fn get_button_action(&self, button: i32) -> Option<Action>
+	{
+		self.buttons.iter().find(|&&(b, _)| b == button).map(|&(_, a)| a)
+	}
+}
+
+/* Turns keyboard and joystick events into InputEvents. Anything else (e.g. a test) can feed it through inject.
+ * While a menu is open presses only go to the menu, otherwise only to the game, so one press
+ * can't e.g. close the pause menu and open it again. */
+pub struct Input
+{
+	pub bindings: Bindings,
+	joystick: JoystickMap,
+	axes: Vec<(i32, i32, i32)>,
+	capturing: bool,
+	menus_open: bool,
+	events: Vec<InputEvent>,
+}
+
+impl Input
+{
+	pub fn new(config: &Config) -> Input
+	{
+		Input
+		{
+			bindings: Bindings::load(config),
+			joystick: JoystickMap::load(config),
+			axes: Vec::new(),
+			capturing: false,
+			menus_open: false,
+			events: Vec::new(),
+		}
+	}
+	
+	pub fn save(&self, config: &mut Config)
+	{
+		self.bindings.save(config);
+		self.joystick.save(config);
+	}
+	
+	pub fn inject(&mut self, event: InputEvent)
+	{
+		self.events.push(event);
+	}
+	
+	pub fn poll(&mut self) -> Vec<InputEvent>
+	{
+		replace(&mut self.events, Vec::new())
+	}
+	
+	/* Whether presses from now on are for the menus or the game */
+	pub fn set_menus_open(&mut self, open: bool)
+	{
+		self.menus_open = open;
+	}
+	
+	/* The next key press is reported as a KeyCaptured and nothing else */
+	pub fn capture_key(&mut self)
+	{
+		self.capturing = true;
+	}
+	
+	pub fn key_down(&mut self, k: key::KeyCode)
+	{
+		if self.capturing
+		{
+			self.capturing = false;
+			self.inject(KeyCaptured(k));
+			return;
+		}
+		
+		if self.menus_open
+		{
+			menu_action_for_key(k).map(|m| self.inject(MenuPressed(m)));
+		}
+		for a in self.bindings.get_actions(k).move_iter()
+		{
+			// Screenshots and recordings work in the menus too
+			if !self.menus_open || a == ScreenshotAction || a == RecordAction
+			{
+				self.inject(ActionPressed(a));
+			}
+		}
+	}
+	
+	pub fn key_up(&mut self, k: key::KeyCode)
+	{
+		for a in self.bindings.get_actions(k).move_iter()
+		{
+			self.inject(ActionReleased(a));
+		}
+	}
+	
+	pub fn joystick_axis(&mut self, stick: i32, axis: i32, pos: f32)
+	{
+		if axis > 1
+		{
+			return;
+		}
+		
+		let dir = if pos < -self.joystick.dead_zone
+		{
+			-1
+		}
+		else if pos > self.joystick.dead_zone
+		{
+			1
+		}
+		else
+		{
+			0
+		};
+		
+		let old_dir = match self.axes.iter().position(|&(s, a, _)| s == stick && a == axis)
+		{
+			Some(idx) =>
+			{
+				let old_dir = self.axes.get(idx).val2();
+				*self.axes.get_mut(idx) = (stick, axis, dir);
+				old_dir
+			},
+			None =>
+			{
+				self.axes.push((stick, axis, dir));
+				0
+			}
+		};
+		
+		if dir == old_dir
+		{
+			return;
+		}
+		
+		let is_move = stick == self.joystick.move_stick;
+		let is_drill = stick == self.joystick.drill_stick || stick == self.joystick.dpad_stick;
+		if !is_move && !is_drill
+		{
+			return;
+		}
+		
+		let action_for = |d: i32| -> Action
+		{
+			match (is_move, axis, d)
+			{
+				(true, 0, -1) => MoveLeftAction,
+				(true, 0, _) => MoveRightAction,
+				(true, _, -1) => MoveUpAction,
+				(true, _, _) => MoveDownAction,
+				(false, 0, -1) => DrillLeftAction,
+				(false, 0, _) => DrillRightAction,
+				(false, _, -1) => DrillUpAction,
+				(false, _, _) => DrillDownAction,
+			}
+		};
+		
+		if old_dir != 0
+		{
+			self.inject(ActionReleased(action_for(old_dir)));
+		}
+		if dir != 0
+		{
+			if !self.menus_open
+			{
+				self.inject(ActionPressed(action_for(dir)));
+			}
+			else if stick == self.joystick.move_stick || stick == self.joystick.dpad_stick
+			{
+				self.inject(MenuPressed(match (axis, dir)
+				{
+					(0, -1) => MenuLeft,
+					(0, _) => MenuRight,
+					(_, -1) => MenuUp,
+					(_, _) => MenuDown,
+				}));
+			}
+		}
+	}
+	
+	pub fn joystick_button(&mut self, button: i32, down: bool)
+	{
+		let action = match self.joystick.get_button_action(button)
+		{
+			Some(a) => a,
+			None => return
+		};
+		
+		if down && self.menus_open
+		{
+			match action
+			{
+				JumpAction => self.inject(MenuPressed(MenuSelect)),
+				PlaceSupportAction | PauseAction => self.inject(MenuPressed(MenuBack)),
+				_ => ()
+			}
+		}
+		else if down
+		{
+			self.inject(ActionPressed(action));
+		}
+		else
+		{
+			self.inject(ActionReleased(action));
+		}
+	}
+}
+
+#[cfg(test)]
+mod test
+{
+	use allegro5::key;
+
+	use config::Config;
+	use menu::*;
+	use super::*;
+
+	#[test]
+	fn escape_in_pause_menu_only_goes_back()
+	{
+		let mut input = Input::new(&Config::new());
+		input.set_menus_open(true);
+		input.key_down(key::Escape);
+		assert!(input.poll() == vec![MenuPressed(MenuBack)]);
+	}
+
+	#[test]
+	fn escape_in_game_pauses()
+	{
+		let mut input = Input::new(&Config::new());
+		input.key_down(key::Escape);
+		assert!(input.poll() == vec![ActionPressed(PauseAction)]);
+		input.key_up(key::Escape);
+		assert!(input.poll() == vec![ActionReleased(PauseAction)]);
+	}
+
+	#[test]
+	fn menu_keys_dont_reach_the_game()
+	{
+		let mut input = Input::new(&Config::new());
+		input.set_menus_open(true);
+		input.key_down(key::Space);
+		input.key_down(key::Enter);
+		input.key_down(key::F12);
+		assert!(input.poll() == vec![MenuPressed(MenuSelect), MenuPressed(MenuSelect), ActionPressed(ScreenshotAction)]);
+	}
+
+	#[test]
+	fn captured_key_is_all_that_is_sent()
+	{
+		let mut input = Input::new(&Config::new());
+		input.capture_key();
+		input.key_down(key::Escape);
+		input.key_down(key::Space);
+		assert!(input.poll() == vec![KeyCaptured(key::Escape), ActionPressed(JumpAction)]);
+	}
+
+	#[test]
+	fn joystick_pause_button()
+	{
+		let mut input = Input::new(&Config::new());
+		input.joystick_button(7, true);
+		input.joystick_button(7, false);
+		assert!(input.poll() == vec![ActionPressed(PauseAction), ActionReleased(PauseAction)]);
+
+		input.set_menus_open(true);
+		input.joystick_button(7, true);
+		input.joystick_button(0, true);
+		assert!(input.poll() == vec![MenuPressed(MenuBack), MenuPressed(MenuSelect)]);
+	}
+
+	#[test]
+	fn joystick_sticks()
+	{
+		let mut input = Input::new(&Config::new());
+		input.joystick_axis(0, 0, -1.0);
+		input.joystick_axis(0, 0, -0.9);
+		input.joystick_axis(0, 0, 1.0);
+		input.joystick_axis(0, 0, 0.1);
+		input.joystick_axis(1, 1, 1.0);
+		assert!(input.poll() == vec![ActionPressed(MoveLeftAction), ActionReleased(MoveLeftAction), ActionPressed(MoveRightAction),
+		                                    ActionReleased(MoveRightAction), ActionPressed(DrillDownAction)]);
+
+		input.set_menus_open(true);
+		input.joystick_axis(0, 1, -1.0);
+		input.joystick_axis(2, 0, 1.0);
+		input.joystick_axis(1, 0, 1.0);
+		assert!(input.poll() == vec![MenuPressed(MenuUp), MenuPressed(MenuRight)]);
+	}
+
+	#[test]
+	fn injected_events_come_out_in_order()
+	{
+		let mut input = Input::new(&Config::new());
+		input.inject(ActionPressed(JumpAction));
+		input.key_down(key::Left);
+		input.inject(ActionReleased(JumpAction));
+		assert!(input.poll() == vec![ActionPressed(JumpAction), ActionPressed(MoveLeftAction), ActionReleased(JumpAction)]);
+		assert!(input.poll().is_empty());
+	}
+
+	#[test]
+	fn rebinding_to_a_used_key_swaps()
+	{
+		let mut bindings = Bindings::new();
+		bindings.set_key(JumpAction, key::Up);
+		assert!(bindings.get_keys(JumpAction) == &[key::Up]);
+		assert!(bindings.get_keys(MoveUpAction) == &[key::Space]);
+	}
+
+	#[test]
+	fn rebinding_leaves_other_keys_alone()
+	{
+		let mut config = Config::new();
+		config.set("keys", "jump", "Space, Up");
+		let mut bindings = Bindings::load(&config);
+		bindings.set_key(MoveLeftAction, key::Up);
+		// Jump still has Space, so it doesn't need Left
+		assert!(bindings.get_keys(JumpAction) == &[key::Space]);
+		assert!(bindings.get_keys(MoveUpAction) == &[key::Left]);
+	}
+}
@@ -0,0 +1,158 @@
+use allegro5::*;
+use allegro_font::*;
+use allegro_ttf::*;
+
+use std::io::fs::readdir;
+
+use config::Config;
+
+static LANG_DIR: &'static str = "data/lang";
+
+/* The text for one language, from data/lang/<code>.cfg:
+ *
+ * [language]
+ * name = Deutsch
+ * font = data/fonts/DejaVuSans.ttf
+ * font_size = 10
+ *
+ * [strings]
+ * Start = Starten
+ * Press a key for '{1}' (Escape to cancel) = Taste für '{1}' drücken (Escape bricht ab)
+ *
+ * The keys are the English text, and anything missing stays in English. Without a font the
+ * builtin one is used, which only has ASCII, so other scripts need a TTF font.
+ */
+pub struct Strings
+{
+	code: ~str,
+	name: ~str,
+	font: Option<(~str, i32)>,
+	table: Config,
+}
+
+impl Strings
+{
+	/* Plain English */
+	pub fn new() -> Strings
+	{
+		Strings
+		{
+			code: ~"en",
+			name: ~"English",
+			font: None,
+			table: Config::new(),
+		}
+	}
+
+	pub fn load(code: &str) -> Strings
+	{
+		let filename = Strings::get_filename(code);
+		match Config::load(filename.as_slice())
+		{
+			Some(table) => Strings
+			{
+				code: code.to_owned(),
+				name: table.get_str("language", "name", code),
+				font: table.get("language", "font").map(|f| (f.to_owned(), table.get_i32("language", "font_size", 10))),
+				table: table,
+			},
+			None =>
+			{
+				println!("Could not load {}, using English", filename);
+				Strings::new()
+			}
+		}
+	}
+
+	/* Reads `code` from the [language] section */
+	pub fn load_config(config: &Config) -> Strings
+	{
+		Strings::load(config.get_str("language", "code", "en").as_slice())
+	}
+
+	pub fn save(&self, config: &mut Config)
+	{
+		config.set("language", "code", self.code.as_slice());
+	}
+
+	/* Where the language with this code is read from */
+	pub fn get_filename(code: &str) -> ~str
+	{
+		format!("{}/{}.cfg", LANG_DIR, code)
+	}
+
+	pub fn get_code<'l>(&'l self) -> &'l str
+	{
+		self.code.as_slice()
+	}
+
+	pub fn get_name<'l>(&'l self) -> &'l str
+	{
+		self.name.as_slice()
+	}
+
+	/* The translation of some English text */
+	pub fn tr<'l>(&'l self, text: &'l str) -> &'l str
+	{
+		self.table.get("strings", text).unwrap_or(text)
+	}
+
+	/* Translates the text and fills in {1}, {2}... with the arguments, in whatever order the
+	 * translation puts them */
+	pub fn fill(&self, text: &str, args: &[&str]) -> ~str
+	{
+		let mut ret = self.tr(text).to_owned();
+		for (i, arg) in args.iter().enumerate()
+		{
+			ret = ret.replace(format!("\\{{}\\}", i + 1).as_slice(), *arg);
+		}
+		ret
+	}
+
+	/* The font this language is written in */
+	pub fn load_font(&self, font_addon: &FontAddon, ttf: &TtfAddon) -> Font
+	{
+		match self.font
+		{
+			Some((ref filename, size)) => match ttf.load_ttf_font(filename.as_slice(), size, Flag::zero())
+			{
+				Some(font) => return font,
+				None => println!("Could not load {}, using the builtin font", *filename)
+			},
+			None => ()
+		}
+		font_addon.create_builtin_font().unwrap()
+	}
+}
+
+/* The code of the language dir steps away from the given one */
+pub fn cycle_language(code: &str, dir: i32) -> ~str
+{
+	let codes = get_languages();
+	let n = codes.len() as i32;
+	let idx = match codes.iter().position(|c| c.as_slice() == code)
+	{
+		Some(idx) => (idx as i32 + dir + n) % n,
+		None => 0
+	};
+	codes.get(idx as uint).clone()
+}
+
+/* Codes of all the languages in data/lang, sorted */
+pub fn get_languages() -> Vec<~str>
+{
+	let mut codes: Vec<~str> = match readdir(&Path::new(LANG_DIR))
+	{
+		Ok(paths) => paths.iter()
+			.filter(|p| p.extension_str() == Some("cfg"))
+			.filter_map(|p| p.filestem_str().map(|s| s.to_owned()))
+			.collect(),
+		Err(_) => Vec::new()
+	};
+	if !codes.contains(&~"en")
+	{
+		codes.push(~"en");
+	}
+	codes.sort();
+	codes
+}
@@ -0,0 +1,121 @@
+use allegro5::*;
+use allegro_font::*;
+
+#[deriving(Eq, Clone)]
+pub enum MenuAction
+{
+	MenuUp,
+	MenuDown,
+	MenuLeft,
+	MenuRight,
+	MenuSelect,
+	MenuBack,
+}
+
+/* Menus are always navigable with these, regardless of the bindings */
+pub fn menu_action_for_key(k: key::KeyCode) -> Option<MenuAction>
+{
+	match k
+	{
+		key::Up => Some(MenuUp),
+		key::Down => Some(MenuDown),
+		key::Left => Some(MenuLeft),
+		key::Right => Some(MenuRight),
+		key::Enter | key::Space => Some(MenuSelect),
+		key::Escape => Some(MenuBack),
+		_ => None
+	}
+}
+
+pub enum MenuInput<T>
+{
+	Chosen(T),
+	Adjusted(T, i32),
+}
+
+pub struct Menu<T>
+{
+	title: ~str,
+	items: Vec<(T, ~str)>,
+	selected: uint,
+	back: T,
+}
+
+impl<T: Clone> Menu<T>
+{
+	pub fn new(title: &str, back: T, items: Vec<(T, ~str)>) -> Menu<T>
+	{
+		Menu
+		{
+			title: title.to_owned(),
+			items: items,
+			selected: 0,
+			back: back,
+		}
+	}
+
+	pub fn set_title(&mut self, title: &str)
+	{
+		self.title = title.to_owned();
+	}
+
+	/* Replaces the labels, keeping the selection where it was */
+	pub fn set_items(&mut self, items: Vec<(T, ~str)>)
+	{
+		self.items = items;
+		if self.selected >= self.items.len()
+		{
+			self.selected = 0;
+		}
+	}
+
+	pub fn get_selected(&self) -> T
+	{
+		self.items.get(self.selected).ref0().clone()
+	}
+
+	pub fn input(&mut self, action: MenuAction) -> Option<MenuInput<T>>
+	{
+		let n = self.items.len();
+		match action
+		{
+			MenuUp =>
+			{
+				self.selected = (self.selected + n - 1) % n;
+				None
+			},
+			MenuDown =>
+			{
+				self.selected = (self.selected + 1) % n;
+				None
+			},
+			MenuLeft => Some(Adjusted(self.get_selected(), -1)),
+			MenuRight => Some(Adjusted(self.get_selected(), 1)),
+			MenuSelect => Some(Chosen(self.get_selected())),
+			MenuBack => Some(Chosen(self.back.clone())),
+		}
+	}
+
+	pub fn draw(&self, core: &Core, font: &Font, dw: i32, dh: i32)
+	{
+		let white = core.map_rgb_f(1.0, 1.0, 1.0);
+		let grey = core.map_rgb_f(0.6, 0.6, 0.6);
+
+		let mut y = dh / 2 - (self.items.len() as i32 * 12 + 20) / 2;
+		core.draw_text(font, white, (dw / 2) as f32, y as f32, AlignCentre, self.title.as_slice());
+		y += 20;
+
+		for (i, &(_, ref label)) in self.items.iter().enumerate()
+		{
+			if i == self.selected
+			{
+				core.draw_text(font, white, (dw / 2) as f32, y as f32, AlignCentre, format!("> {} <", *label));
+			}
+			else
+			{
+				core.draw_text(font, grey, (dw / 2) as f32, y as f32, AlignCentre, label.as_slice());
+			}
+			y += 12;
+		}
+	}
+}
@@ -1,99 +1,121 @@
 use allegro5::*;
 use allegro_font::*;
+use allegro_primitives::*;
 
+use std::cell::Cell;
 use std::cmp::{max, min};
+use std::mem::replace;
 
 use gfx::Gfx;
+use input::*;
+use lang::Strings;
+
+/* How wide the text in the portrait boxes can get */
+static BOX_TEXT_WIDTH: i32 = 272;
+/* How many log lines fit on the screen at once, at most */
+static LOG_LINES: i32 = 20;
 
 pub struct Message
 {
-	lines: Vec<~str>,
+	pub name: ~str,
+	pub priority: i32,
+	// Each is word wrapped separately
+	paragraphs: Vec<~str>,
+	// In characters, not bytes
 	progress: uint,
 	total_len: uint,
-	ready_to_hide: bool,
 	char_timeout: i32,
 	hide_timeout: i32,
 	message_type: MessageType,
 	duration: i32,
-	max_width: f32,
+	logged: bool,
 	pub typing: bool,
 }
 
-#[deriving(Eq)]
-enum MessageType
+#[deriving(Eq, Clone)]
+pub enum MessageType
 {
 	RadioMessage,
 	JohnMessage,
 	CenteredMessage
 }
 
-impl Message
+impl MessageType
+{
+	/* Who's talking, as shown in the log */
+	fn get_speaker(&self) -> Option<&'static str>
+	{
+		match *self
+		{
+			RadioMessage => Some("Radio"),
+			JohnMessage => Some("John"),
+			CenteredMessage => None,
+		}
+	}
+}
+
+/* Breaks the text into lines no wider than width, between words where possible */
+pub fn wrap_text(font: &Font, text: &str, width: i32) -> Vec<~str>
 {
-	pub fn new(message_type: MessageType, duration: i32, lines: &[~str]) -> Message
+	let mut lines = Vec::new();
+	let mut line = ~"";
+	for word in text.words()
 	{
-		let mut max_width = 0.0f32;
-		let mut total_len = 0;
-		for line in lines.iter()
+		let sep = if line.len() == 0 { "" } else { " " };
+		let joined = line + sep + word;
+		if font.get_text_width(joined.as_slice()) <= width
 		{
-			total_len += line.len();
-			max_width = max_width.max(line.len() as f32 * 8.0);
+			line = joined;
+			continue;
 		}
+		if line.len() > 0
+		{
+			lines.push(replace(&mut line, ~""));
+		}
+		// Words too long for a line of their own, as in scripts without spaces, break anywhere
+		for c in word.chars()
+		{
+			let mut next = line.clone();
+			next.push_char(c);
+			if line.len() > 0 && font.get_text_width(next.as_slice()) > width
+			{
+				lines.push(replace(&mut line, ~""));
+				line.push_char(c);
+			}
+			else
+			{
+				line = next;
+			}
+		}
+	}
+	if line.len() > 0
+	{
+		lines.push(line);
+	}
+	lines
+}
+
+impl Message
+{
+	pub fn new(name: &str, message_type: MessageType, duration: i32, priority: i32, paragraphs: &[~str]) -> Message
+	{
+		let total_len = paragraphs.iter().fold(0, |len, p| len + p.char_len());
 		Message
 		{
-			lines: Vec::from_slice(lines),
+			name: name.to_owned(),
+			priority: priority,
+			paragraphs: Vec::from_slice(paragraphs),
 			progress: 0,
-			ready_to_hide: false,
 			char_timeout: 0,
 			hide_timeout: 0,
 			total_len: total_len,
 			message_type: message_type,
 			duration: duration,
-			max_width: max_width,
+			logged: false,
 			typing: true,
 		}
 	}
 	
-	pub fn intro() -> Message
-	{
-		Message::new(RadioMessage, 240,
-		[~"The Philosopher's Stone is buried",
-		 ~"deep beneath the earth. Dig it up",
-		 ~"and return to the surface.",
-		 ~"Press F1 for help."])
-	}
-
-	pub fn found() -> Message
-	{
-		Message::new(JohnMessage, 240,
-		[~"This must be it... Can I escape",
-		 ~"this hellish place?"])
-	}
-
-	pub fn surface() -> Message
-	{
-		Message::new(JohnMessage, 240,
-		[~"The daylight! I'm out! No more",
-		 ~"demons!"])
-	}
-
-	pub fn no_john() -> Message
-	{
-		Message::new(CenteredMessage, 240,
-		[~"No John. You are the demons."])
-	}
-
-	pub fn eaten() -> Message
-	{
-		Message::new(CenteredMessage, 240,
-		[~"Eaten by a demon!"])
-	}
-
-	pub fn crushed() -> Message
-	{
-		Message::new(CenteredMessage, 240,
-		[~"Crushed by collapsing rock!"])
-	}
-	
 	pub fn update(&mut self) -> bool
 	{
 		self.char_timeout = max(0, self.char_timeout - 1);
@@ -126,17 +148,53 @@ impl Message
 		}
 	}
 	
+	/* Shows the rest of the text at once, or if it's all there already hides the message */
+	pub fn skip(&mut self)
+	{
+		if self.progress <= self.total_len
+		{
+			self.progress = self.total_len + 1;
+			self.hide_timeout = self.duration;
+			self.typing = false;
+		}
+		else
+		{
+			self.hide_timeout = 0;
+		}
+	}
+	
+	/* The wrapped lines, each with how many characters of the text it uses up */
+	fn layout(&self, font: &Font, width: i32) -> Vec<(~str, uint)>
+	{
+		let mut ret = Vec::new();
+		for paragraph in self.paragraphs.iter()
+		{
+			let lines = wrap_text(font, paragraph.as_slice(), width);
+			let n = lines.len();
+			for (i, line) in lines.move_iter().enumerate()
+			{
+				// The space a line was broken at is gone, but still typed out
+				let len = line.char_len() + if i + 1 < n { 1 } else { 0 };
+				ret.push((line, len));
+			}
+		}
+		ret
+	}
+	
 	pub fn draw(&self, gfx: &Gfx, dw: i32, dh: i32, core: &Core, font: &Font)
 	{
 		let mut chars_left = self.progress;
+		let line_height = font.get_line_height() + 2;
 
-		let (x, mut y) = if self.message_type == CenteredMessage
+		let (lines, x, mut y) = if self.message_type == CenteredMessage
 		{
-			(dw / 2 - self.max_width as i32 / 2, dh / 2 + 25)
+			let lines = self.layout(font, dw - 40);
+			let max_width = lines.iter().fold(0, |w, &(ref line, _)| max(w, font.get_text_width(line.as_slice())));
+			(lines, dw / 2 - max_width / 2, dh / 2 + 25)
 		}
 		else
 		{
-			(dw / 2 - 105, dh - 70)
+			(self.layout(font, BOX_TEXT_WIDTH), dw / 2 - 105, dh - 70)
 		};
 		
 		if self.message_type == RadioMessage
@@ -148,41 +206,224 @@ impl Message
 			gfx.john_message.draw(core, dw / 2 - 175, y - 10);
 		}
 		
-		for line in self.lines.iter()
+		for &(ref line, len) in lines.iter()
 		{
-			let chars_to_show = min(line.len(), chars_left);
+			let chars_to_show = min(line.char_len(), chars_left);
 			if chars_to_show == 0
 			{
 				return;
 			}
-			core.draw_text(font, core.map_rgb_f(1.0, 1.0, 1.0), x as f32, y as f32, AlignLeft, line.slice_to(chars_to_show));
+			core.draw_text(font, core.map_rgb_f(1.0, 1.0, 1.0), x as f32, y as f32, AlignLeft, line.slice_chars(0, chars_to_show));
 			
-			y += 10;
-			chars_left -= chars_to_show;
+			y += line_height;
+			chars_left -= min(len, chars_left);
 		}
 	}
 
-	pub fn draw_help(dw: i32, _dh: i32, core: &Core, font: &Font)
+	pub fn draw_help(dw: i32, _dh: i32, core: &Core, font: &Font, strings: &Strings, bindings: &Bindings)
 	{
+		let join = |actions: &[Action]| -> ~str
+		{
+			let names: Vec<~str> = actions.iter().map(|a| bindings.describe(*a, "/")).collect();
+			names.as_slice().connect("/")
+		};
+		
 		let help = 
 		[
-		    "Controls",
-		    "",
-		    "Arrows  - Move",
-			"W/A/S/D - Dig",
-			"Space   - Jump",
-			"R       - Place support",
-			"T       - Place torch",
+			(join([MoveLeftAction, MoveRightAction, MoveUpAction, MoveDownAction]), strings.tr("Move")),
+			(join([DrillLeftAction, DrillRightAction, DrillUpAction, DrillDownAction]), strings.tr("Dig")),
+			(join([JumpAction]), strings.tr("Jump")),
+			(join([PlaceSupportAction]), strings.tr("Place support")),
+			(join([PlaceTorchAction]), strings.tr("Place torch")),
+			(join([SkipMessageAction]), strings.tr("Skip message")),
+			(join([LogAction]), strings.tr("Message log")),
+			(join([PeekUpAction, PeekDownAction]), strings.tr("Look up/down")),
+			(join([ZoomAction]), strings.tr("Zoom")),
 		];
 		
+		// Measured rather than padded with spaces, since the font might not be fixed width
+		let mut width = 0;
+		for &(ref keys, _) in help.iter()
+		{
+			width = max(width, font.get_text_width(keys.as_slice()));
+		}
+		let dash_width = font.get_text_width(" - ");
+		
 		let x = dw / 2 - 80;
 		
 		let mut y = 40;
 		
-		for &line in help.iter()
+		let white = core.map_rgb_f(1.0, 1.0, 1.0);
+		core.draw_text(font, white, x as f32, y as f32, AlignLeft, strings.tr("Controls"));
+		y += 20;
+		
+		for &(ref keys, desc) in help.iter()
 		{
-			core.draw_text(font, core.map_rgb_f(1.0, 1.0, 1.0), x as f32, y as f32, AlignLeft, line);
+			core.draw_text(font, white, x as f32, y as f32, AlignLeft, keys.as_slice());
+			core.draw_text(font, white, (x + width) as f32, y as f32, AlignLeft, " - ");
+			core.draw_text(font, white, (x + width + dash_width) as f32, y as f32, AlignLeft, desc);
 			y += 10;
 		}
 	}
 }
+
+/* The message being shown, the ones waiting their turn, most important first, and a log of
+ * everything shown so far */
+pub struct MessageQueue
+{
+	current: Option<Message>,
+	waiting: Vec<Message>,
+	log: Vec<(MessageType, ~str)>,
+	// Lines up from the bottom of the log. How far it can go depends on the wrapping, so
+	// draw_log keeps it in range.
+	log_scroll: Cell<i32>,
+}
+
+impl MessageQueue
+{
+	pub fn new() -> MessageQueue
+	{
+		MessageQueue
+		{
+			current: None,
+			waiting: Vec::new(),
+			log: Vec::new(),
+			log_scroll: Cell::new(0),
+		}
+	}
+
+	/* Shows the message right away if nothing is showing or it's more important than what is,
+	 * which then waits to be finished. Otherwise it waits behind anything at least as important. */
+	pub fn push(&mut self, message: Message)
+	{
+		match self.current.take()
+		{
+			None => self.show(message),
+			Some(current) =>
+			{
+				if message.priority > current.priority
+				{
+					self.wait(current, true);
+					self.show(message);
+				}
+				else
+				{
+					self.current = Some(current);
+					self.wait(message, false);
+				}
+			}
+		}
+	}
+
+	fn wait(&mut self, message: Message, first: bool)
+	{
+		let idx = self.waiting.iter().position(|m| if first { m.priority <= message.priority } else { m.priority < message.priority });
+		let idx = idx.unwrap_or(self.waiting.len());
+		self.waiting.insert(idx, message);
+	}
+
+	fn show(&mut self, mut message: Message)
+	{
+		if !message.logged
+		{
+			for paragraph in message.paragraphs.iter()
+			{
+				self.log.push((message.message_type, paragraph.clone()));
+			}
+			message.logged = true;
+			self.log_scroll.set(0);
+		}
+		self.current = Some(message);
+	}
+
+	/* Drops everything that's showing or waiting, the log stays */
+	pub fn clear(&mut self)
+	{
+		self.current = None;
+		self.waiting.clear();
+	}
+
+	pub fn is_empty(&self) -> bool
+	{
+		self.current.is_none() && self.waiting.is_empty()
+	}
+
+	pub fn is_typing(&self) -> bool
+	{
+		self.current.as_ref().map_or(false, |m| m.typing)
+	}
+
+	pub fn skip(&mut self)
+	{
+		self.current.as_mut().map(|m| m.skip());
+	}
+
+	/* Returns the name of the message that just went away. The next one only comes up on the
+	 * following update, so anything pushed in response goes straight on. */
+	pub fn update(&mut self) -> Option<~str>
+	{
+		if self.current.is_none() && !self.waiting.is_empty()
+		{
+			let next = self.waiting.shift().unwrap();
+			self.show(next);
+		}
+
+		let done = match self.current
+		{
+			Some(ref mut m) => m.update(),
+			None => false
+		};
+		if done
+		{
+			self.current.take().map(|m| m.name)
+		}
+		else
+		{
+			None
+		}
+	}
+
+	pub fn scroll_log(&mut self, lines: i32)
+	{
+		self.log_scroll.set(max(self.log_scroll.get() + lines, 0));
+	}
+
+	pub fn draw(&self, gfx: &Gfx, dw: i32, dh: i32, core: &Core, font: &Font)
+	{
+		self.current.as_ref().map(|m| m.draw(gfx, dw, dh, core, font));
+	}
+
+	pub fn draw_log(&self, core: &Core, prim: &PrimitivesAddon, font: &Font, strings: &Strings, dw: i32, dh: i32)
+	{
+		let line_height = font.get_line_height() + 2;
+		let x = 20;
+		let width = dw - 2 * x;
+		let mut lines = Vec::new();
+		for &(message_type, ref text) in self.log.iter()
+		{
+			let text = match message_type.get_speaker()
+			{
+				Some(speaker) => strings.fill("{1}: {2}", [strings.tr(speaker), text.as_slice()]),
+				None => text.clone()
+			};
+			lines.push_all_move(wrap_text(font, text.as_slice(), width));
+		}
+
+		let rows = min(LOG_LINES, (dh - 60) / line_height);
+		let scroll = min(self.log_scroll.get(), max(lines.len() as i32 - rows, 0));
+		self.log_scroll.set(scroll);
+		let end = lines.len() as i32 - scroll;
+		let start = max(end - rows, 0);
+
+		let top = 30;
+		prim.draw_filled_rectangle((x - 6) as f32, (top - 20) as f32, (dw - x + 6) as f32, (top + rows * line_height + 6) as f32, core.map_rgba_f(0.0, 0.0, 0.0, 0.8));
+		core.draw_text(font, core.map_rgb_f(1.0, 1.0, 1.0), (dw / 2) as f32, (top - 14) as f32, AlignCentre, strings.tr("Message log"));
+
+		let mut y = top;
+		for line in lines.slice(start as uint, end as uint).iter()
+		{
+			core.draw_text(font, core.map_rgb_f(0.8, 0.8, 0.8), x as f32, y as f32, AlignLeft, line.as_slice());
+			y += line_height;
+		}
+	}
+}
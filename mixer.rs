@@ -0,0 +1,136 @@
+use config::Config;
+
+#[deriving(Eq, Clone)]
+pub enum Bus
+{
+	MasterBus,
+	EffectsBus,
+	AmbienceBus,
+	UiBus,
+	MusicBus,
+}
+
+pub static ALL_BUSES: &'static [Bus] = &[
+	MasterBus,
+	EffectsBus,
+	AmbienceBus,
+	UiBus,
+	MusicBus,
+];
+
+impl Bus
+{
+	/* Name used in the config and sound definition files */
+	pub fn get_name(&self) -> &'static str
+	{
+		match *self
+		{
+			MasterBus => "master",
+			EffectsBus => "effects",
+			AmbienceBus => "ambience",
+			UiBus => "ui",
+			MusicBus => "music",
+		}
+	}
+
+	pub fn get_description(&self) -> &'static str
+	{
+		match *self
+		{
+			MasterBus => "Master",
+			EffectsBus => "Effects",
+			AmbienceBus => "Ambience",
+			UiBus => "Interface",
+			MusicBus => "Music",
+		}
+	}
+
+	pub fn from_name(name: &str) -> Option<Bus>
+	{
+		ALL_BUSES.iter().find(|b| b.get_name() == name).map(|b| *b)
+	}
+
+	fn get_index(&self) -> uint
+	{
+		ALL_BUSES.iter().position(|b| b == self).unwrap()
+	}
+}
+
+/* Volume and mute per bus. Every bus feeds into the master one. */
+pub struct Mixer
+{
+	volumes: Vec<f32>,
+	muted: Vec<bool>,
+}
+
+impl Mixer
+{
+	pub fn new() -> Mixer
+	{
+		Mixer
+		{
+			volumes: ALL_BUSES.iter().map(|b| if *b == MusicBus { 0.7 } else { 1.0 }).collect(),
+			muted: ALL_BUSES.iter().map(|_| false).collect(),
+		}
+	}
+
+	/* Reads the [mixer] section, e.g. `music = 0.7` and `music_muted = false` */
+	pub fn load(config: &Config) -> Mixer
+	{
+		let mut mixer = Mixer::new();
+		for bus in ALL_BUSES.iter()
+		{
+			let volume = config.get_f32("mixer", bus.get_name(), mixer.get_volume(*bus));
+			mixer.set_volume(*bus, volume);
+			*mixer.muted.get_mut(bus.get_index()) = config.get_bool("mixer", format!("{}_muted", bus.get_name()).as_slice(), false);
+		}
+		mixer
+	}
+
+	pub fn save(&self, config: &mut Config)
+	{
+		for bus in ALL_BUSES.iter()
+		{
+			config.set_f32("mixer", bus.get_name(), self.get_volume(*bus));
+			config.set_bool("mixer", format!("{}_muted", bus.get_name()).as_slice(), self.is_muted(*bus));
+		}
+	}
+
+	pub fn get_volume(&self, bus: Bus) -> f32
+	{
+		*self.volumes.get(bus.get_index())
+	}
+
+	pub fn set_volume(&mut self, bus: Bus, volume: f32)
+	{
+		*self.volumes.get_mut(bus.get_index()) = volume.max(0.0).min(1.0);
+	}
+
+	pub fn is_muted(&self, bus: Bus) -> bool
+	{
+		*self.muted.get(bus.get_index())
+	}
+
+	pub fn toggle_mute(&mut self, bus: Bus)
+	{
+		let muted = self.muted.get_mut(bus.get_index());
+		*muted = !*muted;
+	}
+
+	/* What a sound on this bus actually gets multiplied by */
+	pub fn get_gain(&self, bus: Bus) -> f32
+	{
+		if self.is_muted(bus) || self.is_muted(MasterBus)
+		{
+			0.0
+		}
+		else if bus == MasterBus
+		{
+			self.get_volume(MasterBus)
+		}
+		else
+		{
+			self.get_volume(bus) * self.get_volume(MasterBus)
+		}
+	}
+}
@@ -0,0 +1,126 @@
+use allegro_audio::*;
+
+use config::Config;
+use sfx::Sfx;
+
+pub static MUSIC_DEFS: &'static str = "data/music.cfg";
+/* How long a track takes to fade fully in or out */
+static CROSSFADE_TICKS: f32 = 120.0;
+
+struct Track
+{
+	name: ~str,
+	filename: ~str,
+	stream: AudioStream,
+	gain: f32,
+	target: f32,
+}
+
+/* Streamed, looping tracks that fade between each other. Which tracks exist is up to
+ * data/music.cfg, with one section per track:
+ *
+ * [deep]
+ * file = data/music_deep.ogg
+ *
+ * Tracks that are missing or fail to load are simply silent. */
+pub struct Music
+{
+	tracks: Vec<Track>,
+}
+
+impl Music
+{
+	pub fn new(audio: &AudioAddon, sfx: &mut Sfx) -> Music
+	{
+		let mut tracks = Vec::new();
+		let defs = Config::load(MUSIC_DEFS).unwrap_or_else(|| Config::new());
+		for name in defs.get_sections().iter()
+		{
+			let filename = defs.get_str(name.as_slice(), "file", format!("data/music_{}.ogg", *name));
+			if !Path::new(filename.as_slice()).exists()
+			{
+				continue;
+			}
+			match audio.load_audio_stream(filename.as_slice())
+			{
+				Some(mut stream) =>
+				{
+					stream.set_playmode(PlaymodeLoop);
+					stream.set_gain(0.0);
+					stream.set_playing(false);
+					sfx.attach_stream(&mut stream);
+					tracks.push(Track
+					{
+						name: name.clone(),
+						filename: filename.clone(),
+						stream: stream,
+						gain: 0.0,
+						target: 0.0,
+					});
+				},
+				None => println!("Could not load music track {}", filename)
+			}
+		}
+
+		Music
+		{
+			tracks: tracks,
+		}
+	}
+
+	/* Rereads the tracks if the definitions or one of the tracks changed. They fade back in
+	 * to the mix they had. */
+	pub fn reload(&mut self, audio: &AudioAddon, sfx: &mut Sfx, filename: &str)
+	{
+		if filename != MUSIC_DEFS && !self.tracks.iter().any(|t| t.filename.as_slice() == filename)
+		{
+			return;
+		}
+		let mix: Vec<(~str, f32)> = self.tracks.iter().map(|t| (t.name.clone(), t.target)).collect();
+		// Let go of the old streams first, they're attached to the same sink
+		self.tracks.clear();
+		*self = Music::new(audio, sfx);
+		for track in self.tracks.mut_iter()
+		{
+			track.target = mix.iter().find(|&&(ref name, _)| *name == track.name).map_or(0.0, |&(_, level)| level);
+		}
+	}
+
+	/* Sets how loud each named track should be, everything not mentioned fades out */
+	pub fn set_mix(&mut self, mix: &[(&str, f32)])
+	{
+		for track in self.tracks.mut_iter()
+		{
+			track.target = mix.iter().find(|&&(name, _)| name == track.name.as_slice()).map_or(0.0, |&(_, level)| level);
+		}
+	}
+
+	/* Steps the crossfades, with gain coming from the music bus */
+	pub fn update(&mut self, gain: f32)
+	{
+		let step = 1.0 / CROSSFADE_TICKS;
+		for track in self.tracks.mut_iter()
+		{
+			if track.gain < track.target
+			{
+				track.gain = (track.gain + step).min(track.target);
+			}
+			else if track.gain > track.target
+			{
+				track.gain = (track.gain - step).max(track.target);
+			}
+
+			// Silent tracks are stopped, so they start from the top next time
+			let playing = track.gain > 0.0;
+			if playing != track.stream.get_playing()
+			{
+				if !playing
+				{
+					track.stream.rewind();
+				}
+				track.stream.set_playing(playing);
+			}
+			track.stream.set_gain(track.gain * gain);
+		}
+	}
+}
@@ -0,0 +1,155 @@
+use allegro5::*;
+use allegro_primitives::*;
+
+use rand::Rng;
+
+use world::World;
+use camera::Camera;
+
+static MAX_PARTICLES: uint = 1000;
+
+struct Particle
+{
+	x: f32,
+	y: f32,
+	vx: f32,
+	vy: f32,
+	gravity: f32,
+	life: i32,
+	max_life: i32,
+	size: f32,
+	color: (f32, f32, f32),
+	emissive: bool,
+}
+
+pub struct ParticleSystem
+{
+	particles: Vec<Particle>,
+}
+
+impl ParticleSystem
+{
+	pub fn new() -> ParticleSystem
+	{
+		ParticleSystem
+		{
+			particles: Vec::new()
+		}
+	}
+
+	fn emit(&mut self, x: f32, y: f32, vx: f32, vy: f32, gravity: f32, life: i32, size: f32, color: (f32, f32, f32), emissive: bool)
+	{
+		if self.particles.len() >= MAX_PARTICLES
+		{
+			return;
+		}
+		
+		self.particles.push(Particle
+		{
+			x: x,
+			y: y,
+			vx: vx,
+			vy: vy,
+			gravity: gravity,
+			life: life,
+			max_life: life,
+			size: size,
+			color: color,
+			emissive: emissive,
+		});
+	}
+	
+	/* Chips flying off a tile being drilled */
+	pub fn debris<R: Rng>(&mut self, rng: &mut R, x: i32, y: i32)
+	{
+		for _ in range(0, 2)
+		{
+			let shade = rng.gen_range(0.35f32, 0.55);
+			self.emit(x as f32 + rng.gen_range(-12.0f32, 12.0), y as f32 + rng.gen_range(-12.0f32, 12.0),
+			          rng.gen_range(-1.5f32, 1.5), rng.gen_range(-2.5f32, 0.0), 0.15,
+			          rng.gen_range(20, 40), rng.gen_range(1.0f32, 2.5), (shade, shade * 0.75, shade * 0.55), false);
+		}
+	}
+	
+	/* Cloud kicked up by a falling tile landing */
+	pub fn dust<R: Rng>(&mut self, rng: &mut R, x: i32, y: i32)
+	{
+		for _ in range(0, 24)
+		{
+			let shade = rng.gen_range(0.5f32, 0.7);
+			self.emit(x as f32 + rng.gen_range(-16.0f32, 16.0), y as f32 + rng.gen_range(8.0f32, 16.0),
+			          rng.gen_range(-1.0f32, 1.0), rng.gen_range(-0.8f32, 0.0), -0.005,
+			          rng.gen_range(40, 80), rng.gen_range(2.0f32, 4.0), (shade, shade * 0.9, shade * 0.8), false);
+		}
+	}
+	
+	pub fn footstep<R: Rng>(&mut self, rng: &mut R, x: i32, y: i32)
+	{
+		for _ in range(0, 3)
+		{
+			let shade = rng.gen_range(0.4f32, 0.6);
+			self.emit(x as f32 + rng.gen_range(-4.0f32, 4.0), y as f32 - 1.0,
+			          rng.gen_range(-0.5f32, 0.5), rng.gen_range(-0.6f32, -0.2), 0.05,
+			          rng.gen_range(10, 20), 1.0, (shade, shade * 0.85, shade * 0.7), false);
+		}
+	}
+	
+	pub fn sparkles<R: Rng>(&mut self, rng: &mut R, x: i32, y: i32, color: (f32, f32, f32))
+	{
+		for _ in range(0, 12)
+		{
+			let angle = rng.gen_range(0.0f32, 6.2832);
+			let speed = rng.gen_range(0.5f32, 2.0);
+			self.emit(x as f32, y as f32, angle.cos() * speed, angle.sin() * speed, 0.0,
+			          rng.gen_range(15, 30), 1.5, color, true);
+		}
+	}
+	
+	pub fn embers<R: Rng>(&mut self, rng: &mut R, x: i32, y: i32)
+	{
+		self.emit(x as f32 + rng.gen_range(-2.0f32, 2.0), y as f32 - 4.0,
+		          rng.gen_range(-0.3f32, 0.3), rng.gen_range(-0.8f32, -0.3), -0.01,
+		          rng.gen_range(20, 45), 1.0, (1.0, rng.gen_range(0.4f32, 0.7), 0.1), true);
+	}
+	
+	pub fn update(&mut self, world: &World)
+	{
+		for p in self.particles.mut_iter()
+		{
+			p.vy += p.gravity;
+			p.x += p.vx;
+			p.y += p.vy;
+			p.life -= 1;
+			
+			// Solid particles stop at walls
+			if !p.emissive && world.colliding(p.x as i32, p.y as i32, 1, 1)
+			{
+				p.life = 0;
+			}
+		}
+		self.particles.retain(|p| p.life > 0);
+	}
+	
+	pub fn draw(&self, core: &Core, prim: &PrimitivesAddon, world: &World, camera: &Camera)
+	{
+		for p in self.particles.iter()
+		{
+			let l = if p.emissive
+			{
+				1.0
+			}
+			else
+			{
+				(0.02 + 0.98 * world.get_light(p.x as i32, p.y as i32)).min(1.0)
+			};
+			let a = p.life as f32 / p.max_life as f32;
+			let (r, g, b) = p.color;
+			let color = core.map_rgba_f(r * l * a, g * l * a, b * l * a, a);
+			
+			let x = p.x - camera.x as f32;
+			let y = p.y - camera.y as f32;
+			let s = p.size / 2.0;
+			prim.draw_filled_rectangle(x - s, y - s, x + s, y + s, color);
+		}
+	}
+}
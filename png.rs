@@ -0,0 +1,128 @@
+/* A minimal PNG writer, so frames can be encoded away from the thread that owns Allegro. The
+ * image data is stored rather than compressed, which makes big files but is quick. */
+
+static SIGNATURE: &'static [u8] = &[137, 80, 78, 71, 13, 10, 26, 10];
+/* The most a stored deflate block can hold */
+static MAX_BLOCK: uint = 65535;
+
+fn push_be_u32(out: &mut Vec<u8>, n: u32)
+{
+	out.push((n >> 24) as u8);
+	out.push((n >> 16) as u8);
+	out.push((n >> 8) as u8);
+	out.push(n as u8);
+}
+
+fn push_le_u16(out: &mut Vec<u8>, n: u16)
+{
+	out.push(n as u8);
+	out.push((n >> 8) as u8);
+}
+
+pub struct Encoder
+{
+	crc_table: Vec<u32>,
+}
+
+impl Encoder
+{
+	pub fn new() -> Encoder
+	{
+		let crc_table = range(0u32, 256).map(|n|
+		{
+			let mut c = n;
+			for _ in range(0, 8)
+			{
+				c = if c & 1 == 1 { 0xEDB88320 ^ (c >> 1) } else { c >> 1 };
+			}
+			c
+		}).collect();
+		Encoder
+		{
+			crc_table: crc_table,
+		}
+	}
+
+	fn crc32(&self, data: &[u8]) -> u32
+	{
+		let mut c = 0xFFFFFFFFu32;
+		for &b in data.iter()
+		{
+			c = *self.crc_table.get(((c ^ b as u32) & 0xFF) as uint) ^ (c >> 8);
+		}
+		c ^ 0xFFFFFFFF
+	}
+
+	fn push_chunk(&self, out: &mut Vec<u8>, kind: &[u8], data: &[u8])
+	{
+		push_be_u32(out, data.len() as u32);
+		let start = out.len();
+		out.push_all(kind);
+		out.push_all(data);
+		let crc = self.crc32(out.as_slice().slice_from(start));
+		push_be_u32(out, crc);
+	}
+
+	/* Encodes 8 bit RGB pixels, row by row from the top */
+	pub fn encode(&self, width: uint, height: uint, rgb: &[u8]) -> Vec<u8>
+	{
+		assert!(rgb.len() == width * height * 3);
+
+		// Each row starts with its filter, none here
+		let mut raw = Vec::with_capacity((width * 3 + 1) * height);
+		for row in rgb.chunks(width * 3)
+		{
+			raw.push(0u8);
+			raw.push_all(row);
+		}
+
+		let mut zlib = vec![0x78u8, 0x01];
+		let num_blocks = (raw.len() + MAX_BLOCK - 1) / MAX_BLOCK;
+		for (i, block) in raw.as_slice().chunks(MAX_BLOCK).enumerate()
+		{
+			zlib.push(if i + 1 == num_blocks { 1 } else { 0 });
+			push_le_u16(&mut zlib, block.len() as u16);
+			push_le_u16(&mut zlib, !(block.len() as u16));
+			zlib.push_all(block);
+		}
+		let (mut a, mut b) = (1u32, 0u32);
+		for &byte in raw.iter()
+		{
+			a = (a + byte as u32) % 65521;
+			b = (b + a) % 65521;
+		}
+		push_be_u32(&mut zlib, (b << 16) | a);
+
+		let mut header = Vec::new();
+		push_be_u32(&mut header, width as u32);
+		push_be_u32(&mut header, height as u32);
+		// 8 bits per channel, RGB, then the default compression, filtering and no interlacing
+		header.push_all([8u8, 2, 0, 0, 0]);
+
+		let mut out = Vec::from_slice(SIGNATURE);
+		self.push_chunk(&mut out, "IHDR".as_bytes(), header.as_slice());
+		self.push_chunk(&mut out, "IDAT".as_bytes(), zlib.as_slice());
+		self.push_chunk(&mut out, "IEND".as_bytes(), []);
+		out
+	}
+}
+
+#[cfg(test)]
+mod test
+{
+	use super::{Encoder, SIGNATURE};
+
+	#[test]
+	fn single_pixel()
+	{
+		let png = Encoder::new().encode(1, 1, [255, 0, 0]);
+		let png = png.as_slice();
+		assert!(png.slice_to(8) == SIGNATURE);
+		// IEND's CRC never changes
+		let iend_crc: &[u8] = &[0xAE, 0x42, 0x60, 0x82];
+		assert!(png.slice_from(png.len() - 4) == iend_crc);
+		// The row, behind its filter byte, in a single stored block
+		let block: &[u8] = &[1, 4, 0, 0xFB, 0xFF, 0, 255, 0, 0];
+		assert!(png.windows(block.len()).any(|w| w == block));
+	}
+}
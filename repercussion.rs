@@ -7,8 +7,10 @@
 extern crate allegro5;
 extern crate allegro_image;
 extern crate allegro_font;
+extern crate allegro_ttf;
 extern crate allegro_audio;
 extern crate allegro_acodec;
+extern crate allegro_primitives;
 extern crate num;
 extern crate rand;
 extern crate time;
@@ -16,18 +18,28 @@ extern crate time;
 use allegro5::*;
 use allegro_image::*;
 use allegro_font::*;
+use allegro_ttf::*;
 use allegro_audio::*;
 use allegro_acodec::*;
+use allegro_primitives::*;
 
-use world::{World, SURFACE_HEIGHT};
-use camera::Camera;
-use entity::*;
-use gem::{Gem, Purple, Phil};
-use fun::Demon;
-use torch::Torch;
-use message::Message;
+use std::os;
+use game::Game;
 use gfx::Gfx;
-use sfx::Sfx;
+use sfx::{Sfx, SOUND_DEFS};
+use dialogue::DIALOGUE_DEFS;
+use cutscene::CUTSCENE_DEFS;
+use menu::{Menu, Chosen, Adjusted};
+use config::Config;
+use input::*;
+use screen::ScreenSettings;
+use camera::CameraSettings;
+use debug::{FrameTimer, DebugOff};
+use watcher::AssetWatcher;
+use capture::Capture;
+use music::{Music, MUSIC_DEFS};
+use mixer::{Mixer, Bus, ALL_BUSES, MusicBus};
+use lang::{Strings, cycle_language};
 
 mod camera;
 mod world;
@@ -40,14 +52,143 @@ mod message;
 mod sprite;
 mod gfx;
 mod sfx;
+mod event;
+mod stats;
+mod game;
+mod menu;
+mod config;
+mod input;
+mod screen;
+mod debug;
+mod particle;
+mod backdrop;
+mod watcher;
+mod atlas;
+mod headless;
+mod capture;
+mod png;
+mod spatial;
+mod music;
+mod ambience;
+mod dialogue;
+mod cutscene;
+mod mixer;
+mod lang;
+
+static CONFIG_FILE: &'static str = "repercussion.cfg";
 
 #[deriving(Eq, Clone)]
-enum GameState
+enum MenuItem
+{
+	StartGame,
+	ResumeGame,
+	OpenOptions,
+	OpenAudio,
+	BusVolume(Bus),
+	Resolution,
+	Fullscreen,
+	Scaling,
+	ViewSize,
+	CameraFollow,
+	Zoom,
+	ScreenShake,
+	Language,
+	OpenControls,
+	Rebind(Action),
+	ResetControls,
+	QuitToTitle,
+	QuitGame,
+	ConfirmQuit,
+	Back,
+}
+
+fn title_menu(strings: &Strings) -> Menu<MenuItem>
+{
+	Menu::new(strings.tr("Repercussion"), QuitGame,
+	vec![(StartGame, strings.tr("Start").to_owned()),
+	     (OpenOptions, strings.tr("Options").to_owned()),
+	     (QuitGame, strings.tr("Quit").to_owned())])
+}
+
+fn pause_menu(strings: &Strings) -> Menu<MenuItem>
+{
+	Menu::new(strings.tr("Paused"), ResumeGame,
+	vec![(ResumeGame, strings.tr("Resume").to_owned()),
+	     (OpenOptions, strings.tr("Options").to_owned()),
+	     (QuitToTitle, strings.tr("Quit to title").to_owned()),
+	     (QuitGame, strings.tr("Quit game").to_owned())])
+}
+
+fn options_items(screen: &ScreenSettings, camera: &CameraSettings, strings: &Strings) -> Vec<(MenuItem, ~str)>
 {
-	Playing,
-	Dead,
-	Won,
-	Ending,
+	let size = |w: i32, h: i32| format!("{}x{}", w, h);
+	let shake = if camera.shake > 0.0 { format!("{}%", (camera.shake * 100.0 + 0.5) as i32) } else { strings.tr("Off").to_owned() };
+	vec![(OpenAudio, strings.tr("Audio").to_owned()),
+	     (Resolution, strings.fill("Resolution: {1}", [size(screen.width, screen.height).as_slice()])),
+	     (Fullscreen, strings.fill("Fullscreen: {1}", [strings.tr(if screen.fullscreen { "On" } else { "Off" })])),
+	     (Scaling, strings.fill("Scaling: {1}", [strings.tr(if screen.integer_scaling { "Integer" } else { "Fit" })])),
+	     (ViewSize, strings.fill("View size: {1}", [size(screen.view_width, screen.view_height).as_slice()])),
+	     (CameraFollow, strings.fill("Camera: {1}", [strings.tr(camera.mode.get_description())])),
+	     (Zoom, strings.fill("Zoom: {1}", [format!("{}x", camera.zoom).as_slice()])),
+	     (ScreenShake, strings.fill("Screen shake: {1}", [shake.as_slice()])),
+	     (Language, strings.fill("Language: {1}", [strings.get_name()])),
+	     (OpenControls, strings.tr("Controls").to_owned()),
+	     (Back, strings.tr("Back").to_owned())]
+}
+
+fn audio_items(mixer: &Mixer, strings: &Strings) -> Vec<(MenuItem, ~str)>
+{
+	let mut items: Vec<(MenuItem, ~str)> = ALL_BUSES.iter().map(|b|
+	{
+		(BusVolume(*b), if mixer.is_muted(*b)
+		{
+			strings.fill("{1}: muted", [strings.tr(b.get_description())])
+		}
+		else
+		{
+			let volume = format!("{}", (mixer.get_volume(*b) * 100.0 + 0.5) as i32);
+			strings.fill("{1}: {2}%", [strings.tr(b.get_description()), volume.as_slice()])
+		})
+	}).collect();
+	items.push((Back, strings.tr("Back").to_owned()));
+	items
+}
+
+fn controls_items(bindings: &Bindings, strings: &Strings) -> Vec<(MenuItem, ~str)>
+{
+	let mut items: Vec<(MenuItem, ~str)> = ALL_ACTIONS.iter().map(|a|
+	{
+		(Rebind(*a), format!("{}: {}", strings.tr(a.get_description()), bindings.describe(*a, ", ")))
+	}).collect();
+	items.push((ResetControls, strings.tr("Reset to defaults").to_owned()));
+	items.push((Back, strings.tr("Back").to_owned()));
+	items
+}
+
+fn save_config(config: &mut Config, input: &Input, sfx: &Sfx, screen: &ScreenSettings, camera: &CameraSettings, strings: &Strings)
+{
+	input.save(config);
+	screen.save(config);
+	camera.save(config);
+	sfx.get_mixer().save(config);
+	strings.save(config);
+	config.save(CONFIG_FILE);
+}
+
+fn create_view_buffers(core: &Core, screen: &ScreenSettings) -> (Bitmap, Bitmap)
+{
+	let buffer = core.create_bitmap(screen.view_width, screen.view_height).unwrap();
+	let shade = core.create_bitmap(screen.view_width, screen.view_height).unwrap();
+	core.set_target_bitmap(&shade);
+	core.clear_to_color(core.map_rgb_f(0.0, 0.0, 0.0));
+	(buffer, shade)
+}
+
+fn confirm_quit_menu(strings: &Strings) -> Menu<MenuItem>
+{
+	Menu::new(strings.tr("Really quit?"), Back,
+	vec![(Back, strings.tr("No").to_owned()),
+	     (ConfirmQuit, strings.tr("Yes").to_owned())])
 }
 
 allegro_main!
@@ -55,379 +196,398 @@ allegro_main!
 	let mut core = Core::init().unwrap();
 	ImageAddon::init(&core).expect("Failed to initialize the image addon");
 	let font_addon = FontAddon::init(&core).expect("Failed to initialize the font addon");
+	let ttf = TtfAddon::init(&font_addon).expect("Failed to initialize the ttf addon");
+	let prim = PrimitivesAddon::init(&core).expect("Failed to initialize the primitives addon");
+	
+	// Headless golden image tests: repercussion --golden <dir> [--bless]. These run silently,
+	// so they work without an audio device.
+	let args: Vec<~str> = os::args().move_iter().collect();
+	if args.len() >= 3 && args.get(1).as_slice() == "--golden"
+	{
+		core.set_new_bitmap_flags(MEMORY_BITMAP);
+		let font = font_addon.create_builtin_font().unwrap();
+		let mut gfx = Gfx::new(&core);
+		let mut sfx = Sfx::silent(Mixer::new());
+		let bless = args.iter().any(|a| a.as_slice() == "--bless");
+		// Always in English, so the images don't depend on the settings
+		if !headless::run_golden(&core, &prim, &font, &Strings::new(), &mut gfx, &mut sfx, args.get(2).as_slice(), bless)
+		{
+			os::set_exit_status(1);
+		}
+		return;
+	}
+	
 	let audio = AudioAddon::init(&core).expect("Failed to initialize the audio addon");
 	let _acodec = AcodecAddon::init(&audio).expect("Failed to initialize the acodec addon");
 	
-	let dw = 800;
-	let dh = 600;
+	let mut config = Config::load(CONFIG_FILE).unwrap_or_else(|| Config::new());
+	let mut screen = ScreenSettings::load(&config);
+	let mut camera_settings = CameraSettings::load(&config);
 	
-	let disp = core.create_display(dw, dh).expect("Failed to create display");
+	core.set_new_display_flags(if screen.fullscreen { FULLSCREEN_WINDOW } else { RESIZABLE });
+	let disp = core.create_display(screen.width, screen.height).expect("Failed to create display");
 	disp.set_window_title(&"Repercussion".to_c_str());
 
 	core.install_keyboard();
+	core.install_joystick();
 	
 	let timer = core.create_timer(1.0 / 60.0).unwrap();
 
 	let q = core.create_event_queue().unwrap();
 	q.register_event_source(disp.get_event_source());
 	q.register_event_source(core.get_keyboard_event_source().unwrap());
+	q.register_event_source(core.get_joystick_event_source().unwrap());
 	q.register_event_source(timer.get_event_source());
 	
-	let font = font_addon.create_builtin_font().unwrap();
+	let mut strings = Strings::load_config(&config);
+	let mut font = strings.load_font(&font_addon, &ttf);
 	let black = core.map_rgb_f(0.0, 0.0, 0.0);
-	let white = core.map_rgb_f(1.0, 1.0, 1.0);
 	
 	let mut gfx = Gfx::new(&core);
-	let mut sfx = Sfx::new(&audio);
-	let buffer = core.create_bitmap(dw / 2, dh / 2).unwrap();
+	let mut sfx = Sfx::new(&audio, Mixer::load(&config));
+	let mut music = Music::new(&audio, &mut sfx);
+	
+	let mut input = Input::new(&config);
+	input.set_menus_open(true);
+	
+	// Development mode, reloading assets as they change on disk
+	let hot_reload = config.get_bool("dev", "hot_reload", false);
+	config.set_bool("dev", "hot_reload", hot_reload);
+	let mut watcher = if hot_reload { Some(AssetWatcher::new("data")) } else { None };
+	// Write it back out so there's always a complete file to edit
+	save_config(&mut config, &input, &sfx, &screen, &camera_settings, &strings);
+	
+	let (mut buffer, mut shade) = create_view_buffers(&core, &screen);
 	
+	// The game is running whenever there are no menus open
+	let mut game: Option<Game> = None;
+	let mut menus: Vec<Menu<MenuItem>> = vec![title_menu(&strings)];
+	let mut rebinding: Option<Action> = None;
+	let mut debug_mode = DebugOff;
+	let mut frame_timer = FrameTimer::new();
+	let mut capture = Capture::new();
+	
+	let mut redraw = true;
+	timer.start();
 	'exit: loop
 	{
-		let mut world = World::new(&core, 30, 90);
-		let mut camera = Camera::new(dw / 2, dh / 2, world.get_pixel_width(), world.get_pixel_height());
-		let mut player = Entity::player(20, 20);
-		
-		let mut gems: Vec<Gem> = Vec::new();
-		let mut demons: Vec<Demon> = Vec::new();
-		let mut torches: Vec<Torch> = Vec::new();
-		let mut message = Some(Message::intro());
-		let mut state = Playing;
-		
-		let phil_loc = world.add_caves(
-		|(x, y)|
-		{
-			demons.push(Demon::new(x, y))
-		},
-		|rare, (x, y)|
+		if redraw && q.is_empty()
 		{
-			//~ println!("rare: {}, x: {}, y: {}", rare, x, y);
-			gems.push(if rare
+			core.set_target_bitmap(&buffer);
+			core.clear_to_color(black);
+			
+			game.as_ref().map(|g|
+			{
+				g.draw(&core, &prim, &font, &strings, &gfx, &input.bindings, screen.view_width, screen.view_height);
+				g.camera.use_transform(&core);
+				g.world.draw_debug(&core, &prim, &font, &g.camera, debug_mode);
+				g.camera.reset_transform(&core);
+			});
+			
+			if debug_mode != DebugOff
 			{
-				Gem::with_color(x, y, Purple)
+				frame_timer.draw(&core, &font, debug_mode, 10, screen.view_height - 24);
 			}
-			else
+			
+			menus.last().map(|m|
+			{
+				if game.is_some()
+				{
+					core.draw_tinted_bitmap(&shade, core.map_rgba_f(0.0, 0.0, 0.0, 0.6), 0.0, 0.0, Flag::zero());
+				}
+				m.draw(&core, &font, screen.view_width, screen.view_height);
+			});
+			
+			rebinding.map(|a|
 			{
-				Gem::new(x, y)
+				core.draw_text(&font, core.map_rgb_f(1.0, 1.0, 0.0), (screen.view_width / 2) as f32, (screen.view_height - 20) as f32, AlignCentre,
+				               strings.fill("Press a key for '{1}' (Escape to cancel)", [strings.tr(a.get_description())]));
 			});
-		});
-		
-		let mut phil = Gem::with_color(phil_loc.val0(), phil_loc.val1(), Phil);
-		//~ let mut phil = Gem::with_color(128, 128, Phil);
-		
-		let mut mine_up = false;
-		let mut mine_down = false;
-		let mut mine_left = false;
-		let mut mine_right = false;
-		let mut place_support = false;
-		let mut place_torch = false;
-		let mut gem_count = 20i32;
-		let mut eaten = false;
-		let mut show_help = false;
-		
-		let mut redraw = true;
-		timer.start();
-		'game_loop: loop
+			
+			core.set_target_bitmap(disp.get_backbuffer());
+			core.clear_to_color(black);
+			let (bx, by, bw, bh) = screen.get_blit_rect(disp.get_width(), disp.get_height());
+			core.draw_scaled_bitmap(&buffer, 0.0, 0.0, screen.view_width as f32, screen.view_height as f32, bx, by, bw, bh, Flag::zero());
+			// Drawn outside of the buffer so it doesn't end up in the captures
+			capture.draw(&core, &font, disp.get_width());
+			disp.flip();
+			redraw = false;
+		}
+
+		match q.wait_for_event()
 		{
-			if redraw && q.is_empty()
+			DisplayClose{..} =>
 			{
-				core.set_target_bitmap(&buffer);
-				core.clear_to_color(black);
-				
-				//~ disp.hold_bitmap_drawing(true);
-				if state != Ending && state != Dead
+				break 'exit;
+			},
+			KeyDown{keycode: k, ..} => input.key_down(k),
+			KeyUp{keycode: k, ..} => input.key_up(k),
+			JoystickAxes{stick, axis, pos, ..} => input.joystick_axis(stick, axis, pos),
+			JoystickButtonDown{button, ..} => input.joystick_button(button, true),
+			JoystickButtonUp{button, ..} => input.joystick_button(button, false),
+			DisplayResize{..} =>
+			{
+				disp.acknowledge_resize();
+				if !screen.fullscreen
 				{
-					world.draw(&core, &font, &camera);
-					
-					for t in torches.iter()
-					{
-						t.draw(&gfx, &core, &camera);
-					}
+					screen.width = disp.get_width();
+					screen.height = disp.get_height();
+					save_config(&mut config, &input, &sfx, &screen, &camera_settings, &strings);
 				}
+			},
+			JoystickConfiguration{..} =>
+			{
+				core.reconfigure_joysticks();
+			},
+			TimerTick{..} =>
+			{
+				let start = time::precise_time_ns();
 				
-				player.drill_direction = match (mine_left, mine_right, mine_up, mine_down)
-				{
-					(true, _, _, _) => DrillLeft,
-					(_, true, _, _) => DrillRight,
-					(_, _, true, _) => DrillUp,
-					(_, _, _, true) => DrillDown,
-					_ => DrillNone
-				};
-				
-				player.draw(&gfx, &core, &world, &camera);
-				
-				if state != Ending && state != Dead
+				for path in watcher.as_mut().map_or(Vec::new(), |w| w.poll()).iter()
 				{
-					for d in demons.iter()
+					let filename = path.as_str().unwrap_or("");
+					if filename.ends_with(".ogg") || filename.ends_with(".wav") || filename == SOUND_DEFS
 					{
-						d.draw(&gfx, &core, &world, &camera);
+						sfx.reload(&audio, filename);
+						music.reload(&audio, &mut sfx, filename);
 					}
-					
-					for g in gems.iter()
+					else if filename == MUSIC_DEFS
 					{
-						g.draw(&gfx, &core, &camera);
+						music.reload(&audio, &mut sfx, filename);
 					}
-					
-					phil.draw(&gfx, &core, &camera);
-					
-					gfx.ui_gem.draw(&core, 10, 10);
-					core.draw_text(&font, white, 42.0, 15.0, AlignLeft, format!("x{}", gem_count));
-					
-					if show_help
+					else if filename == Strings::get_filename(strings.get_code()).as_slice()
 					{
-						Message::draw_help(dw / 2, dh / 2, &core, &font);
+						strings = Strings::load(strings.get_code());
+						font = strings.load_font(&font_addon, &ttf);
+						game.as_mut().map(|g| g.script.reload(DIALOGUE_DEFS, &strings, &input.bindings));
 					}
-				}
-				
-				message.as_ref().map(|m|
-				{
-					m.draw(&gfx, dw / 2, dh / 2, &core, &font);
-				});
-				
-				core.set_target_bitmap(disp.get_backbuffer());
-				core.draw_scaled_bitmap(&buffer, 0.0, 0.0, (dw / 2) as f32, (dh / 2) as f32, 0.0, 0.0, dw as f32, dh as f32, Flag::zero());
-				disp.flip();
-				redraw = false;
-			}
-
-			match q.wait_for_event()
-			{
-				DisplayClose{..} =>
-				{
-					break 'exit;
-				},
-				KeyDown{keycode: k, ..} =>
-				{
-					if state == Ending || state == Dead
+					else if filename.ends_with(".cfg") && filename.starts_with("data/lang/")
 					{
-						break 'game_loop;
+						// Some other language, it's read when switched to
 					}
-					match k
+					else if filename == DIALOGUE_DEFS
 					{
-						key::Escape => break 'exit,
-						key::Left => player.want_left = true,
-						key::Right => player.want_right = true,
-						key::Up => player.want_up = true,
-						key::Down => player.want_down = true,
-						key::A => mine_left = true,
-						key::D => mine_right = true,
-						key::W => mine_up = true,
-						key::S => mine_down = true,
-						key::R => place_support = true,
-						key::T => place_torch = true,
-						key::Space => player.jump(&world),
-						key::F1 => show_help = true,
-						_ => ()
+						game.as_mut().map(|g| g.script.reload(filename, &strings, &input.bindings));
 					}
-				},
-				KeyUp{keycode: k, ..} => 
-				{
-					match k
+					else if filename == CUTSCENE_DEFS
 					{
-						key::Left => player.want_left = false,
-						key::Right => player.want_right = false,
-						key::Up => player.want_up = false,
-						key::Down => player.want_down = false,
-						key::A => mine_left = false,
-						key::D => mine_right = false,
-						key::W => mine_up = false,
-						key::S => mine_down = false,
-						key::R => place_support = false,
-						key::T => place_torch = false,
-						key::F1 => show_help = false,
-						_ => ()
+						game.as_mut().map(|g| g.cutscenes.reload(filename));
 					}
-				},
-				TimerTick{..} =>
+					else
+					{
+						gfx.reload(&core, filename);
+						game.as_mut().map(|g| g.world.set_tile_sprite(gfx.tiles.clone()));
+					}
+				}
+				
+				for event in input.poll().move_iter()
 				{
-					let _start = time::precise_time_ns();
-					
-					if state == Playing
+					match event
 					{
-						// Player
-						let old_player_dead = player.dead;
-						player.update(&world);
-						
-						if player.dead
-						{
-							state = Dead;
-							sfx.play_dead();
-							message = if eaten { Some(Message::eaten()) } else { Some(Message::crushed()) };
-						}
-						
-						if !player.dead
+						KeyCaptured(k) =>
 						{
-							sfx.walk_instance.set_playing(player.want_left || player.want_right);
-							sfx.drill_instance.set_playing(player.drill_direction != DrillNone);
-						}
-						
-						if message.is_none()
-						{
-							world.get_tile_coords(player.x + player.w / 2, player.y + player.h / 2).map(|(_, ty)|
+							let action = rebinding.take().unwrap();
+							if k != key::Escape
 							{
-								if phil.dead && ty <= SURFACE_HEIGHT as uint && state == Playing
-								{
-									message = Some(Message::surface());
-									state = Won;
-								}
-							});
-						}
-						
-						// Gems
-						let old_gem_count = gem_count;
-						for g in gems.mut_iter()
-						{
-							gem_count += g.update(&world, player.x, player.y, player.w, player.h);
-						}
-						gems.retain(|g| !g.dead);
-						if old_gem_count != gem_count
-						{
-							sfx.play_gem();
-						}
-
-						// Demons
-						let old_num_demons = demons.len();
-						for d in demons.mut_iter()
-						{
-							eaten |= d.update(&world, player.x, player.y, player.w, player.h);
-						}
-						demons.retain(|d| !d.dead);
-						if demons.len() < old_num_demons
-						{
-							sfx.play_fun();
-						}
-						
-						if eaten
-						{
-							player.dead = true;
-						}
-						
-						// Phil
-						let phil_old_dead = phil.dead;
-						phil.update(&world, player.x, player.y, player.w, player.h);
-						if phil.dead && !phil_old_dead
-						{
-							torches.clear();
-							sfx.play_phil();
-							world.need_new_light = true;
-							message = Some(Message::found());
-						}
-
-						// Torches
-						for t in torches.mut_iter()
-						{
-							t.update(&world);
-						}
-						let old_len = torches.len();
-						torches.retain(|d| !d.dead);
-						
-						if torches.len() != old_len
-						{
-							world.need_new_light = true;
-						}
-						
-						// World
-						let any_falling = world.update(&mut camera, torches.as_slice(), player.x, player.y, player.w, player.h);
-						
-						sfx.collapse_instance.set_playing(any_falling);
-						
-						// Camera
-						camera.update(player.x, player.y);
-						
-						if old_player_dead == false && player.dead
+								input.bindings.set_key(action, k);
+								// The help hint names the key
+								game.as_mut().map(|g| g.script.reload(DIALOGUE_DEFS, &strings, &input.bindings));
+								save_config(&mut config, &input, &sfx, &screen, &camera_settings, &strings);
+								menus.mut_last().unwrap().set_items(controls_items(&input.bindings, &strings));
+							}
+						},
+						ActionPressed(ScreenshotAction) => capture.screenshot(&core, &buffer, &strings),
+						ActionPressed(RecordAction) => capture.toggle_recording(&strings),
+						ActionPressed(ZoomAction) if menus.is_empty() =>
 						{
-							gfx.skeleton.reset(&core);
-						}
-					}
-					else
-					{
-						sfx.collapse_instance.set_playing(false);
-						sfx.drill_instance.set_playing(false);
-						sfx.walk_instance.set_playing(false);
-					}
-					
-					// Messages
-					let hide = message.as_mut().map(|m|
-					{
-						sfx.typing_instance.set_playing(m.typing);
-						m.update()
-					});
-					hide.map(|hide|
-						if hide
+							camera_settings.cycle_zoom(1);
+							game.as_mut().map(|g| g.camera.set_settings(&camera_settings));
+							save_config(&mut config, &input, &sfx, &screen, &camera_settings, &strings);
+						},
+						ActionPressed(action) if menus.is_empty() =>
 						{
-							sfx.typing_instance.set_playing(false);
-							message = None;
-							if state == Won
+							let g = game.get_mut_ref();
+							if action == DebugAction
 							{
-								state = Ending;
-								message = Some(Message::no_john());
+								debug_mode = debug_mode.next();
 							}
-							else if state == Ending
+							else if action == PauseAction
 							{
-								player.make_demon();
-								sfx.play_end();
+								g.release_actions();
+								sfx.pause();
+								menus.push(pause_menu(&strings));
 							}
-						}
-					);
-					//~ println!("{} {}", player.x, player.y);
-					
-					// Player actions
-					if state == Playing && !player.dead &&
-					   (world.on_ground(player.x, player.y, player.w, player.h) || world.on_support(player.x, player.y, player.w, player.h)) &&
-						player.vx == 0 && player.vy == 0
-					{
-						let spawn_gem = match (mine_left, mine_right, mine_up, mine_down)
-						{
-							(true, _, _, _) => world.mine(player.x, player.y, -1,  0),
-							(_, true, _, _) => world.mine(player.x, player.y,  1,  0),
-							(_, _, true, _) => world.mine(player.x, player.y,  0, -1),
-							(_, _, _, true) => world.mine(player.x, player.y,  0,  1),
-							_ => None
-						};
-						
-						spawn_gem.map(|(x, y)|
-						{
-							gems.push(Gem::new(x, y));
-						});
-						
-						if place_support
-						{
-							if gem_count > 1 && world.place_support(player.x, player.y)
+							else if g.is_over()
 							{
-								gem_count -= 2;
-								sfx.play_place();
+								*g = Game::new(&gfx, &strings, &input.bindings, &camera_settings, screen.view_width, screen.view_height);
 							}
 							else
 							{
-								sfx.play_invalid();
+								g.action_down(action);
 							}
-							place_support = false;
-						}
-
-						if place_torch
+						},
+						ActionReleased(action) if menus.is_empty() =>
 						{
-							if gem_count > 0 && Torch::place_torch(&world, &mut torches, player.x, player.y, player.w, player.h)
+							game.get_mut_ref().action_up(action);
+						},
+						MenuPressed(menu_action) if !menus.is_empty() && rebinding.is_none() =>
+						{
+							let (item, dir) = match menus.mut_last().unwrap().input(menu_action)
+							{
+								Some(Chosen(item)) => (item, 0),
+								Some(Adjusted(item, dir)) => (item, dir),
+								None => continue,
+							};
+							// Choosing an option steps it forward
+							let step = if dir == 0 { 1 } else { dir };
+							
+							match item
 							{
-								gem_count -= 1;
-								world.need_new_light = true;
-								sfx.play_place();
+								BusVolume(bus) =>
+								{
+									// Left and right change the volume, choosing it mutes
+									if dir == 0
+									{
+										sfx.toggle_mute(bus);
+									}
+									else
+									{
+										let volume = sfx.get_mixer().get_volume(bus) + dir as f32 * 0.1;
+										sfx.set_bus_volume(bus, volume);
+									}
+									save_config(&mut config, &input, &sfx, &screen, &camera_settings, &strings);
+									menus.mut_last().unwrap().set_items(audio_items(sfx.get_mixer(), &strings));
+								},
+								Resolution | Fullscreen | Scaling | ViewSize | CameraFollow | Zoom | ScreenShake | Language =>
+								{
+									match item
+									{
+										Resolution =>
+										{
+											screen.cycle_resolution(step);
+											if !screen.fullscreen
+											{
+												disp.resize(screen.width, screen.height);
+											}
+										},
+										Fullscreen =>
+										{
+											screen.fullscreen = !screen.fullscreen;
+											disp.toggle_flag(FULLSCREEN_WINDOW, screen.fullscreen);
+										},
+										Scaling =>
+										{
+											screen.integer_scaling = !screen.integer_scaling;
+										},
+										ViewSize =>
+										{
+											screen.cycle_view_size(step);
+											let (new_buffer, new_shade) = create_view_buffers(&core, &screen);
+											buffer = new_buffer;
+											shade = new_shade;
+											game.as_mut().map(|g| g.camera.resize(screen.view_width, screen.view_height));
+										},
+										CameraFollow | Zoom | ScreenShake =>
+										{
+											match item
+											{
+												CameraFollow => camera_settings.toggle_mode(),
+												Zoom => camera_settings.cycle_zoom(step),
+												_ => camera_settings.cycle_shake(step)
+											}
+											game.as_mut().map(|g| g.camera.set_settings(&camera_settings));
+										},
+										Language =>
+										{
+											let code = cycle_language(strings.get_code(), step);
+											strings = Strings::load(code.as_slice());
+											font = strings.load_font(&font_addon, &ttf);
+											game.as_mut().map(|g| g.script.reload(DIALOGUE_DEFS, &strings, &input.bindings));
+											// The menus underneath are still in the old language
+											*menus.get_mut(0) = if game.is_some() { pause_menu(&strings) } else { title_menu(&strings) };
+											menus.mut_last().unwrap().set_title(strings.tr("Options"));
+										},
+										_ => unreachable!()
+									}
+									save_config(&mut config, &input, &sfx, &screen, &camera_settings, &strings);
+									menus.mut_last().unwrap().set_items(options_items(&screen, &camera_settings, &strings));
+								},
+								_ if dir != 0 => (),
+								StartGame =>
+								{
+									game = Some(Game::new(&gfx, &strings, &input.bindings, &camera_settings, screen.view_width, screen.view_height));
+									menus.clear();
+								},
+								ResumeGame | Back =>
+								{
+									menus.pop();
+								},
+								OpenAudio =>
+								{
+									menus.push(Menu::new(strings.tr("Audio"), Back, audio_items(sfx.get_mixer(), &strings)));
+								},
+								OpenOptions =>
+								{
+									menus.push(Menu::new(strings.tr("Options"), Back, options_items(&screen, &camera_settings, &strings)));
+								},
+								OpenControls =>
+								{
+									menus.push(Menu::new(strings.tr("Controls"), Back, controls_items(&input.bindings, &strings)));
+								},
+								Rebind(action) =>
+								{
+									rebinding = Some(action);
+									input.capture_key();
+								},
+								ResetControls =>
+								{
+									input.bindings = Bindings::new();
+									save_config(&mut config, &input, &sfx, &screen, &camera_settings, &strings);
+									menus.mut_last().unwrap().set_items(controls_items(&input.bindings, &strings));
+								},
+								QuitToTitle =>
+								{
+									game = None;
+									sfx.stop_all();
+									menus = vec![title_menu(&strings)];
+								},
+								QuitGame =>
+								{
+									menus.push(confirm_quit_menu(&strings));
+								},
+								ConfirmQuit => break 'exit,
 							}
-							else
+							
+							if menus.is_empty()
 							{
-								sfx.play_invalid();
+								sfx.resume();
 							}
-							place_torch = false;
-						}
-						
+						},
+						_ => ()
 					}
-					
-					let _end = time::precise_time_ns();
-					
-					//~ println!("Update duration (ms): {}", (end - start) as f64 / 1e6);
-					
-					redraw = true;
-				},
-				_ => ()
-			}
+				}
+				input.set_menus_open(!menus.is_empty());
+				
+				if menus.is_empty()
+				{
+					let g = game.get_mut_ref();
+					g.update(&mut gfx, &mut sfx);
+					music.set_mix(g.get_music_mix().as_slice());
+				}
+				else if game.is_none()
+				{
+					music.set_mix([("title", 1.0)]);
+				}
+				music.update(sfx.get_mixer().get_gain(MusicBus));
+				
+				capture.update(&core, &buffer, &strings);
+				
+				let end = time::precise_time_ns();
+				frame_timer.add(start, end);
+				
+				redraw = true;
+			},
+			_ => ()
 		}
 	}
 }
@@ -0,0 +1,82 @@
+use config::Config;
+
+pub static RESOLUTIONS: &'static [(i32, i32)] = &[(640, 480), (800, 600), (1024, 768), (1280, 720), (1280, 960), (1600, 900), (1920, 1080)];
+pub static VIEW_SIZES: &'static [(i32, i32)] = &[(320, 240), (400, 300), (480, 270), (512, 384), (640, 360)];
+/* Anything smaller leaves no room for the HUD and menus */
+static MIN_VIEW_SIZE: (i32, i32) = (160, 120);
+
+/* The game is drawn into a view_width x view_height buffer, which is then scaled onto the display */
+pub struct ScreenSettings
+{
+	pub width: i32,
+	pub height: i32,
+	pub fullscreen: bool,
+	pub integer_scaling: bool,
+	pub view_width: i32,
+	pub view_height: i32,
+}
+
+fn cycle(sizes: &[(i32, i32)], cur: (i32, i32), dir: i32) -> (i32, i32)
+{
+	let n = sizes.len() as i32;
+	let idx = match sizes.iter().position(|s| *s == cur)
+	{
+		Some(idx) => (idx as i32 + dir + n) % n,
+		None => 0
+	};
+	sizes[idx as uint]
+}
+
+impl ScreenSettings
+{
+	pub fn load(config: &Config) -> ScreenSettings
+	{
+		let (min_w, min_h) = MIN_VIEW_SIZE;
+		ScreenSettings
+		{
+			width: config.get_i32("display", "width", 800),
+			height: config.get_i32("display", "height", 600),
+			fullscreen: config.get_bool("display", "fullscreen", false),
+			integer_scaling: config.get_str("display", "scaling", "integer").as_slice() != "fit",
+			view_width: config.get_i32("display", "view_width", 400).max(min_w),
+			view_height: config.get_i32("display", "view_height", 300).max(min_h),
+		}
+	}
+
+	pub fn save(&self, config: &mut Config)
+	{
+		config.set_i32("display", "width", self.width);
+		config.set_i32("display", "height", self.height);
+		config.set_bool("display", "fullscreen", self.fullscreen);
+		config.set("display", "scaling", if self.integer_scaling { "integer" } else { "fit" });
+		config.set_i32("display", "view_width", self.view_width);
+		config.set_i32("display", "view_height", self.view_height);
+	}
+
+	pub fn cycle_resolution(&mut self, dir: i32)
+	{
+		let (w, h) = cycle(RESOLUTIONS, (self.width, self.height), dir);
+		self.width = w;
+		self.height = h;
+	}
+
+	pub fn cycle_view_size(&mut self, dir: i32)
+	{
+		let (w, h) = cycle(VIEW_SIZES, (self.view_width, self.view_height), dir);
+		self.view_width = w;
+		self.view_height = h;
+	}
+
+	/* Where the view buffer goes on a display of the given size, centered with black bars around it */
+	pub fn get_blit_rect(&self, disp_w: i32, disp_h: i32) -> (f32, f32, f32, f32)
+	{
+		let mut scale = (disp_w as f32 / self.view_width as f32).min(disp_h as f32 / self.view_height as f32);
+		if self.integer_scaling
+		{
+			scale = scale.floor().max(1.0);
+		}
+		let w = self.view_width as f32 * scale;
+		let h = self.view_height as f32 * scale;
+		(((disp_w as f32 - w) / 2.0).floor(), ((disp_h as f32 - h) / 2.0).floor(), w, h)
+	}
+}
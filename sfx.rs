@@ -1,128 +1,406 @@
 use allegro_audio::*;
 
-pub struct Sfx
+use std::mem::replace;
+use rand::{task_rng, Rng};
+
+use config::Config;
+use mixer::*;
+
+pub static SOUND_DEFS: &'static str = "data/sounds.cfg";
+/* One-shot sounds that can play at the same time, across all sounds */
+static MAX_VOICES: uint = 16;
+
+/* A sound from data/sounds.cfg, one section per sound:
+ *
+ * [gem]
+ * file = data/gem.ogg
+ * bus = effects
+ * gain = 1.0
+ * loop = false
+ * speed = 1.0
+ * priority = 0
+ * max_voices = 4
+ * pitch_variation = 0.05
+ *
+ * Looping sounds are switched on and off every tick by whatever makes them, the rest are played once.
+ * A one-shot played more than max_voices times at once cuts off its oldest copy, and when all voices
+ * are busy it takes over the oldest one with a lower or equal priority. Pitch variation is the most
+ * the playback speed may be randomly moved away from 1 each time.
+ */
+struct Sound
+{
+	name: ~str,
+	filename: ~str,
+	sample: Sample,
+	bus: Bus,
+	gain: f32,
+	looping: bool,
+	speed: f32,
+	priority: i32,
+	max_voices: uint,
+	pitch_variation: f32,
+	// Only looping sounds have one of these, one-shots get voices
+	instance: Option<SampleInstance>,
+	// Gain and pan of the loop from where in the world it is
+	spatial: (f32, f32),
+}
+
+/* A one-shot sound that is playing */
+struct Voice
+{
+	sound: uint,
+	instance: SampleInstance,
+	priority: i32,
+	gain: f32,
+}
+
+impl Sound
+{
+	fn load(audio: &AudioAddon, sink: &mut Sink, defs: &Config, name: &str) -> Option<Sound>
+	{
+		let filename = defs.get_str(name, "file", format!("data/{}.ogg", name));
+		let sample = match audio.load_sample(filename.as_slice())
+		{
+			Some(sample) => sample,
+			None =>
+			{
+				println!("Failed to load {}", filename);
+				return None;
+			}
+		};
+		let bus_name = defs.get_str(name, "bus", "effects");
+		let bus = Bus::from_name(bus_name.as_slice()).unwrap_or_else(||
+		{
+			println!("Unknown bus '{}' for sound {}", bus_name, name);
+			EffectsBus
+		});
+		let looping = defs.get_bool(name, "loop", false);
+		let speed = defs.get_f32(name, "speed", 1.0);
+
+		let instance = if looping
+		{
+			let mut instance = audio.create_sample_instance().unwrap();
+			instance.set_sample(&sample);
+			instance.set_playmode(PlaymodeLoop);
+			instance.set_speed(speed);
+			instance.set_playing(false);
+			instance.attach(sink);
+			Some(instance)
+		}
+		else
+		{
+			None
+		};
+
+		Some(Sound
+		{
+			name: name.to_owned(),
+			filename: filename,
+			sample: sample,
+			bus: bus,
+			gain: defs.get_f32(name, "gain", 1.0),
+			looping: looping,
+			speed: speed,
+			priority: defs.get_i32(name, "priority", 0),
+			max_voices: defs.get_i32(name, "max_voices", 4) as uint,
+			pitch_variation: defs.get_f32(name, "pitch_variation", 0.0),
+			instance: instance,
+			spatial: (1.0, 0.0),
+		})
+	}
+
+	fn apply_gain(&mut self, mixer: &Mixer)
+	{
+		let gain = mixer.get_gain(self.bus) * self.gain * self.spatial.val0();
+		self.instance.as_mut().map(|inst| inst.set_gain(gain));
+	}
+}
+
+fn load_sounds(audio: &AudioAddon, sink: &mut Sink) -> Option<Vec<Sound>>
 {
-	walk_sound: Sample,
-	pub walk_instance: SampleInstance,
-	drill_sound: Sample,
-	pub drill_instance: SampleInstance,
-	collapse_sound: Sample,
-	pub collapse_instance: SampleInstance,
-	typing_sound: Sample,
-	pub typing_instance: SampleInstance,
-	sink: Sink,
-	
-	gem_sound: Sample,
-	gem_instance: Option<SampleInstance>,
-	dead_sound: Sample,
-	dead_instance: Option<SampleInstance>,
-	phil_sound: Sample,
-	phil_instance: Option<SampleInstance>,
-	place_sound: Sample,
-	place_instance: Option<SampleInstance>,
-	invalid_sound: Sample,
-	invalid_instance: Option<SampleInstance>,
-	fun_sound: Sample,
-	fun_instance: Option<SampleInstance>,
-	end_sound: Sample,
-	end_instance: Option<SampleInstance>,
+	let defs = match Config::load(SOUND_DEFS)
+	{
+		Some(defs) => defs,
+		None =>
+		{
+			println!("Failed to load {}", SOUND_DEFS);
+			return None;
+		}
+	};
+	Some(defs.get_sections().iter().filter_map(|name| Sound::load(audio, sink, &defs, name.as_slice())).collect())
 }
 
-pub fn load_sample(audio: &AudioAddon, filename: &str) -> Sample
+pub struct Sfx
 {
-	audio.load_sample(filename).expect(format!("Failed to load {}", filename))
+	// None when silent
+	sink: Option<Sink>,
+	sounds: Vec<Sound>,
+	// Oldest first
+	voices: Vec<Voice>,
+	mixer: Mixer,
+	was_playing: Vec<bool>,
 }
 
 impl Sfx
 {
-	pub fn new(audio: &AudioAddon) -> Sfx
+	pub fn new(audio: &AudioAddon, mixer: Mixer) -> Sfx
 	{
 		let mut sink = audio.create_sink().expect("Failed to create audio sink");
-		
-		let walk_sound = load_sample(audio, "data/walk.ogg");
-		let mut walk_instance = audio.create_sample_instance().unwrap();
-		walk_instance.set_sample(&walk_sound);
-		walk_instance.set_playing(false);
-		walk_instance.attach(&mut sink);
-		
-		let drill_sound = load_sample(audio, "data/drill.ogg");
-		let mut drill_instance = audio.create_sample_instance().unwrap();
-		drill_instance.set_sample(&drill_sound);
-		drill_instance.set_playing(false);
-		drill_instance.attach(&mut sink);
-		
-		let collapse_sound = load_sample(audio, "data/collapse.ogg");
-		let mut collapse_instance = audio.create_sample_instance().unwrap();
-		collapse_instance.set_sample(&collapse_sound);
-		collapse_instance.set_playing(false);
-		collapse_instance.attach(&mut sink);
-		
-		let typing_sound = load_sample(audio, "data/typing.ogg");
-		let mut typing_instance = audio.create_sample_instance().unwrap();
-		typing_instance.set_sample(&typing_sound);
-		typing_instance.set_playing(false);
-		typing_instance.attach(&mut sink);
-		
+		let sounds = load_sounds(audio, &mut sink).expect(format!("Failed to load {}", SOUND_DEFS));
+		let mut sfx = Sfx
+		{
+			sink: Some(sink),
+			sounds: sounds,
+			voices: Vec::new(),
+			mixer: mixer,
+			was_playing: Vec::new(),
+		};
+		sfx.apply_mixer();
+		sfx
+	}
+
+	/* No sounds and no audio device, for the golden image tests */
+	pub fn silent(mixer: Mixer) -> Sfx
+	{
 		Sfx
 		{
-			sink: sink,
-			walk_sound: walk_sound,
-			walk_instance: walk_instance,
-			drill_sound: drill_sound,
-			drill_instance: drill_instance,
-			collapse_sound: collapse_sound,
-			collapse_instance: collapse_instance,
-			typing_sound: typing_sound,
-			typing_instance: typing_instance,
-			gem_sound: load_sample(audio, "data/gem.ogg"),
-			gem_instance: None,
-			phil_sound: load_sample(audio, "data/phil.ogg"),
-			phil_instance: None,
-			dead_sound: load_sample(audio, "data/dead.ogg"),
-			dead_instance: None,
-			place_sound: load_sample(audio, "data/place.ogg"),
-			place_instance: None,
-			invalid_sound: load_sample(audio, "data/invalid.ogg"),
-			invalid_instance: None,
-			fun_sound: load_sample(audio, "data/fun.ogg"),
-			fun_instance: None,
-			end_sound: load_sample(audio, "data/end.ogg"),
-			end_instance: None,
+			sink: None,
+			sounds: Vec::new(),
+			voices: Vec::new(),
+			mixer: mixer,
+			was_playing: Vec::new(),
+		}
+	}
+
+	pub fn attach_stream(&mut self, stream: &mut AudioStream)
+	{
+		self.sink.as_mut().map(|sink| stream.attach(sink));
+	}
+
+	fn find(&self, name: &str) -> Option<uint>
+	{
+		self.sounds.iter().position(|s| s.name.as_slice() == name)
+	}
+
+	fn for_each_instance(&mut self, f: |&mut SampleInstance|)
+	{
+		for sound in self.sounds.mut_iter()
+		{
+			for inst in sound.instance.mut_iter()
+			{
+				f(inst);
+			}
 		}
+		for voice in self.voices.mut_iter()
+		{
+			f(&mut voice.instance);
+		}
+	}
+
+	/* Stops everything that is playing, remembering what it was so resume can restart it */
+	pub fn pause(&mut self)
+	{
+		let mut was_playing = Vec::new();
+		self.for_each_instance(|inst|
+		{
+			was_playing.push(inst.get_playing());
+			inst.set_playing(false);
+		});
+		self.was_playing = was_playing;
+	}
+
+	pub fn resume(&mut self)
+	{
+		let was_playing = replace(&mut self.was_playing, Vec::new());
+		let mut idx = 0;
+		self.for_each_instance(|inst|
+		{
+			if idx < was_playing.len() && *was_playing.get(idx)
+			{
+				inst.set_playing(true);
+			}
+			idx += 1;
+		});
+	}
+
+	/* Stops everything for good, forgetting what was paused */
+	pub fn stop_all(&mut self)
+	{
+		self.for_each_instance(|inst| inst.set_playing(false));
+		self.voices.clear();
+		self.was_playing.clear();
+	}
+
+	pub fn get_mixer<'l>(&'l self) -> &'l Mixer
+	{
+		&self.mixer
+	}
+
+	pub fn set_bus_volume(&mut self, bus: Bus, volume: f32)
+	{
+		self.mixer.set_volume(bus, volume);
+		self.apply_mixer();
 	}
-	
-	pub fn play_gem(&mut self)
+
+	pub fn toggle_mute(&mut self, bus: Bus)
 	{
-		self.gem_instance = self.sink.play_sample(&self.gem_sound, 1.0, None, 1.0, PlaymodeOnce);
+		self.mixer.toggle_mute(bus);
+		self.apply_mixer();
 	}
-	
-	pub fn play_dead(&mut self)
+
+	fn apply_mixer(&mut self)
 	{
-		self.dead_instance = self.sink.play_sample(&self.dead_sound, 1.0, None, 1.0, PlaymodeOnce);
+		for sound in self.sounds.mut_iter()
+		{
+			sound.apply_gain(&self.mixer);
+		}
+		for voice in self.voices.mut_iter()
+		{
+			let sound = self.sounds.get(voice.sound);
+			voice.instance.set_gain(self.mixer.get_gain(sound.bus) * sound.gain * voice.gain);
+		}
 	}
-	
-	pub fn play_phil(&mut self)
+
+	pub fn play(&mut self, name: &str)
 	{
-		self.phil_instance = self.sink.play_sample(&self.phil_sound, 1.0, None, 1.0, PlaymodeOnce);
+		self.play_at(name, (1.0, 0.0));
 	}
-	
-	pub fn play_place(&mut self)
+
+	/* Plays a one-shot sound with the given gain and pan, see spatial::locate */
+	pub fn play_at(&mut self, name: &str, at: (f32, f32))
 	{
-		self.place_instance = self.sink.play_sample(&self.place_sound, 1.0, None, 1.0, PlaymodeOnce);
+		let idx = match self.find(name)
+		{
+			Some(idx) => idx,
+			None => return
+		};
+		let (gain, pan) = at;
+		let (speed, priority, max_voices, pitch_variation) =
+		{
+			let sound = self.sounds.get(idx);
+			(sound.speed, sound.priority, sound.max_voices, sound.pitch_variation)
+		};
+
+		// Finished voices are paused, and not needed any more
+		self.voices.retain(|v| v.instance.get_playing());
+
+		if self.voices.iter().filter(|v| v.sound == idx).count() >= max_voices
+		{
+			match self.voices.iter().position(|v| v.sound == idx)
+			{
+				Some(oldest) => { self.voices.remove(oldest); },
+				None => return
+			}
+		}
+		if self.voices.len() >= MAX_VOICES
+		{
+			let lowest = self.voices.iter().map(|v| v.priority).min().unwrap();
+			if lowest > priority
+			{
+				return;
+			}
+			let oldest = self.voices.iter().position(|v| v.priority == lowest).unwrap();
+			self.voices.remove(oldest);
+		}
+
+		let speed = if pitch_variation > 0.0
+		{
+			speed * (1.0 + task_rng().gen_range(-pitch_variation, pitch_variation))
+		}
+		else
+		{
+			speed
+		};
+		let sink = match self.sink
+		{
+			Some(ref mut sink) => sink,
+			None => return
+		};
+		let sound = self.sounds.get(idx);
+		match sink.play_sample(&sound.sample, self.mixer.get_gain(sound.bus) * sound.gain * gain, Some(pan), speed, PlaymodeOnce)
+		{
+			Some(instance) => self.voices.push(Voice
+			{
+				sound: idx,
+				instance: instance,
+				priority: priority,
+				gain: gain,
+			}),
+			None => ()
+		}
 	}
 
-	pub fn play_invalid(&mut self)
+	pub fn set_playing(&mut self, name: &str, playing: bool)
 	{
-		self.invalid_instance = self.sink.play_sample(&self.invalid_sound, 1.0, None, 1.0, PlaymodeOnce);
+		self.set_playing_at(name, if playing { Some((1.0, 0.0)) } else { None });
 	}
-	
-	pub fn play_fun(&mut self)
+
+	/* Starts or stops a looping sound, placing it if it's playing */
+	pub fn set_playing_at(&mut self, name: &str, at: Option<(f32, f32)>)
 	{
-		self.fun_instance = self.sink.play_sample(&self.fun_sound, 1.0, None, 1.0, PlaymodeOnce);
+		let idx = match self.find(name)
+		{
+			Some(idx) => idx,
+			None => return
+		};
+		let sound = self.sounds.get_mut(idx);
+		match at
+		{
+			Some((gain, pan)) =>
+			{
+				sound.spatial = (gain, pan);
+				sound.apply_gain(&self.mixer);
+				sound.instance.as_mut().map(|inst|
+				{
+					inst.set_pan(Some(pan));
+					inst.set_playing(true);
+				});
+			},
+			None =>
+			{
+				sound.instance.as_mut().map(|inst| inst.set_playing(false));
+			}
+		}
 	}
 
-	pub fn play_end(&mut self)
+	/* Reloads the definitions, or the sounds using the changed file. Sounds that fail to load keep their old version. */
+	pub fn reload(&mut self, audio: &AudioAddon, filename: &str)
 	{
-		self.end_instance = self.sink.play_sample(&self.end_sound, 1.0, None, 1.0, PlaymodeOnce);
+		if filename == SOUND_DEFS
+		{
+			let sink = match self.sink
+			{
+				Some(ref mut sink) => sink,
+				None => return
+			};
+			// Half way through being saved, keep what's playing
+			let sounds = match load_sounds(audio, sink)
+			{
+				Some(sounds) => sounds,
+				None => return
+			};
+			self.voices.clear();
+			self.sounds = sounds;
+			self.apply_mixer();
+			return;
+		}
+
+		for (idx, sound) in self.sounds.mut_iter().enumerate().filter(|&(_, ref s)| s.filename.as_slice() == filename)
+		{
+			let sample = match audio.load_sample(filename)
+			{
+				Some(sample) => sample,
+				None =>
+				{
+					println!("Could not reload {}", filename);
+					return;
+				}
+			};
+			// Instances have to let go of the old sample before it is freed
+			sound.instance.as_mut().map(|inst| inst.set_sample(&sample));
+			self.voices.retain(|v| v.sound != idx);
+			sound.sample = sample;
+			sound.apply_gain(&self.mixer);
+		}
 	}
 }
@@ -0,0 +1,33 @@
+use world::World;
+use camera::Camera;
+
+/* Sounds at this distance from the listener play at half volume */
+static HALF_GAIN_DISTANCE: f32 = 192.0;
+/* Beyond this they're not heard at all */
+static MAX_DISTANCE: f32 = 960.0;
+/* Each solid tile in the way lets this much of the sound through */
+static OCCLUSION_PER_TILE: f32 = 0.6;
+static MIN_OCCLUSION: f32 = 0.15;
+
+/* Gain and stereo pan for a sound at (x, y) in world pixels, heard from the middle of the view */
+pub fn locate(world: &World, camera: &Camera, x: i32, y: i32) -> (f32, f32)
+{
+	let lx = camera.x + camera.width / 2;
+	let ly = camera.y + camera.height / 2;
+	let dx = (x - lx) as f32;
+	let dy = (y - ly) as f32;
+	let dist = (dx * dx + dy * dy).sqrt();
+	if dist >= MAX_DISTANCE
+	{
+		return (0.0, 0.0);
+	}
+
+	let falloff = 1.0 / (1.0 + (dist / HALF_GAIN_DISTANCE) * (dist / HALF_GAIN_DISTANCE));
+	// Fade the last stretch out, so sounds don't cut off abruptly at the edge
+	let edge = ((MAX_DISTANCE - dist) / (MAX_DISTANCE * 0.25)).min(1.0);
+	let solid = world.count_solid_between(lx, ly, x, y);
+	let occlusion = OCCLUSION_PER_TILE.powi(solid).max(MIN_OCCLUSION);
+	let pan = (dx / (camera.width as f32 / 2.0)).max(-1.0).min(1.0);
+
+	(falloff * edge * occlusion, pan)
+}
@@ -1,70 +1,270 @@
 use allegro5::*;
-use allegro5::ffi::al_get_time;
 
 use std::cmp::min;
+use std::rc::Rc;
+use config::Config;
+use atlas::Atlas;
 
+/* Animations are driven by simulation ticks, of which there are this many per second */
+static TICKS_PER_SECOND: f32 = 60.0;
+
+#[deriving(Eq, Clone)]
+pub enum LoopMode
+{
+	LoopForever,
+	PlayOnce,
+	PingPong,
+}
+
+/* A named run of frames in a sheet:
+ *
+ * clip.<name> = <first>-<last> <fps> <loop|once|pingpong> [<event>@<frame> ...]
+ *
+ * Event frames are relative to the start of the clip.
+ */
+#[deriving(Clone)]
+pub struct Clip
+{
+	pub name: ~str,
+	first: i32,
+	last: i32,
+	fps: f32,
+	mode: LoopMode,
+	events: Vec<(i32, ~str)>,
+}
+
+impl Clip
+{
+	pub fn parse(name: &str, def: &str) -> Option<Clip>
+	{
+		let words: Vec<&str> = def.words().collect();
+		if words.len() < 3
+		{
+			return None;
+		}
+
+		let range: Vec<&str> = words.get(0).split('-').collect();
+		let first = from_str::<i32>(*range.get(0));
+		let last = if range.len() > 1 { from_str::<i32>(*range.get(1)) } else { first };
+		let fps = from_str::<f32>(*words.get(1));
+		let mode = match *words.get(2)
+		{
+			"loop" => Some(LoopForever),
+			"once" => Some(PlayOnce),
+			"pingpong" => Some(PingPong),
+			_ => None
+		};
+
+		let mut events = Vec::new();
+		for word in words.slice_from(3).iter()
+		{
+			match word.find('@')
+			{
+				Some(idx) => match from_str::<i32>(word.slice_from(idx + 1))
+				{
+					Some(frame) => events.push((frame, word.slice_to(idx).to_owned())),
+					None => return None
+				},
+				None => return None
+			}
+		}
+
+		match (first, last, fps, mode)
+		{
+			(Some(first), Some(last), Some(fps), Some(mode)) if last >= first => Some(Clip
+			{
+				name: name.to_owned(),
+				first: first,
+				last: last,
+				fps: fps,
+				mode: mode,
+				events: events,
+			}),
+			_ => None
+		}
+	}
+
+	/* Frame within the clip, starting at 0 */
+	fn get_local_frame(&self, ticks: i32) -> i32
+	{
+		let n = self.last - self.first + 1;
+		let f = (ticks as f32 * self.fps / TICKS_PER_SECOND) as i32;
+		match self.mode
+		{
+			LoopForever => f % n,
+			PlayOnce => min(f, n - 1),
+			PingPong =>
+			{
+				if n == 1
+				{
+					0
+				}
+				else
+				{
+					let period = 2 * n - 2;
+					let p = f % period;
+					if p < n { p } else { period - p }
+				}
+			}
+		}
+	}
+
+	pub fn get_frame(&self, ticks: i32) -> i32
+	{
+		self.first + self.get_local_frame(ticks)
+	}
+
+	/* Events attached to the frame that was entered on this tick */
+	pub fn get_events<'l>(&'l self, ticks: i32) -> Vec<&'l str>
+	{
+		let frame = self.get_local_frame(ticks);
+		if ticks > 0 && frame == self.get_local_frame(ticks - 1)
+		{
+			return Vec::new();
+		}
+		self.events.iter().filter(|&&(f, _)| f == frame).map(|&(_, ref e)| e.as_slice()).collect()
+	}
+}
+
+#[deriving(Clone)]
 pub struct Sprite
 {
-	base_bitmap: Bitmap,
+	pub filename: ~str,
+	base_bitmap: Rc<Bitmap>,
+	// Where the sheet sits in base_bitmap, which may be an atlas
+	region_x: i32,
+	region_y: i32,
 	width: i32,
 	height: i32,
 	x_tiles: i32,
 	y_tiles: i32,
-	pub offset: f64
+	clips: Vec<Clip>,
+	pub ticks: i32,
 }
 
 impl Sprite
 {
-	pub fn new(core: &Core, filename: &str, width: i32, height: i32) -> Sprite
+	/* A sheet with a single looping clip over all of its frames */
+	pub fn try_new(core: &Core, filename: &str, width: i32, height: i32) -> Option<Sprite>
+	{
+		core.load_bitmap(filename).map(|bmp|
+		{
+			let (w, h) = (bmp.get_width(), bmp.get_height());
+			Sprite::from_region(filename, Rc::new(bmp), (0, 0, w, h), width, height)
+		})
+	}
+
+	fn from_region(filename: &str, bmp: Rc<Bitmap>, region: (i32, i32, i32, i32), width: i32, height: i32) -> Sprite
 	{
-		let bmp = core.load_bitmap(filename).expect(format!("Could not load {}", filename));
-		let x_tiles = bmp.get_width() / width;
-		let y_tiles = bmp.get_height() / height;
-		
+		let (region_x, region_y, region_w, region_h) = region;
+		let x_tiles = region_w / width;
+		let y_tiles = region_h / height;
+
 		Sprite
 		{
+			filename: filename.to_owned(),
 			base_bitmap: bmp,
+			region_x: region_x,
+			region_y: region_y,
 			width: width,
 			height: height,
 			x_tiles: x_tiles,
 			y_tiles: y_tiles,
-			offset: 0.0
+			clips: vec![Clip
+			{
+				name: ~"default",
+				first: 0,
+				last: x_tiles * y_tiles - 1,
+				fps: 8.0,
+				mode: LoopForever,
+				events: Vec::new(),
+			}],
+			ticks: 0,
 		}
 	}
 
-	pub fn reset(&mut self, _: &Core)
+	pub fn get_filename(defs: &Config, name: &str) -> ~str
+	{
+		defs.get_str(name, "file", format!("data/{}.png", name))
+	}
+
+	/* Loads the sprite described by the [name] section of an animation definition file,
+	 * taking the sheet from the atlas if it is in there */
+	pub fn load(core: &Core, defs: &Config, name: &str, atlas: Option<&Atlas>) -> Sprite
 	{
-		self.offset = 
-		unsafe
+		Sprite::try_load(core, defs, name, atlas).expect(format!("Could not load sprite {}", name))
+	}
+
+	pub fn try_load(core: &Core, defs: &Config, name: &str, atlas: Option<&Atlas>) -> Option<Sprite>
+	{
+		let filename = Sprite::get_filename(defs, name);
+		let width = defs.get_i32(name, "width", 32);
+		let height = defs.get_i32(name, "height", 32);
+		let region = atlas.and_then(|a| a.get_region(filename.as_slice()).map(|r| (a.get_bitmap(), r)));
+		let mut sprite = match region
 		{
-			al_get_time() as f64
+			Some((bmp, region)) => Sprite::from_region(filename.as_slice(), bmp, region, width, height),
+			None => match Sprite::try_new(core, filename.as_slice(), width, height)
+			{
+				Some(sprite) => sprite,
+				None => return None
+			}
 		};
+
+		let mut clips = Vec::new();
+		for key in defs.get_keys(name).iter()
+		{
+			if key.starts_with("clip.")
+			{
+				let clip_name = key.slice_from(5);
+				match Clip::parse(clip_name, defs.get(name, key.as_slice()).unwrap())
+				{
+					Some(clip) => clips.push(clip),
+					None => println!("Ignoring malformed clip {} of sprite {}", clip_name, name)
+				}
+			}
+		}
+		if clips.len() > 0
+		{
+			sprite.clips = clips;
+		}
+		Some(sprite)
 	}
-		
-	
+
+	pub fn update(&mut self)
+	{
+		self.ticks += 1;
+	}
+
+	pub fn reset(&mut self)
+	{
+		self.ticks = 0;
+	}
+
+	fn get_clip<'l>(&'l self, name: &str) -> &'l Clip
+	{
+		self.clips.iter().find(|c| c.name.as_slice() == name).unwrap_or(self.clips.get(0))
+	}
+
+	pub fn get_events<'l>(&'l self, clip: &str) -> Vec<&'l str>
+	{
+		self.get_clip(clip).get_events(self.ticks)
+	}
+
 	pub fn draw(&self, core: &Core, x: i32, y: i32)
 	{
 		self.draw_tinted(core, x, y, core.map_rgb_f(1.0, 1.0, 1.0));
 	}
 
-	pub fn draw_no_loop(&self, core: &Core, x: i32, y: i32)
+	/* Plays the first clip */
+	pub fn draw_tinted(&self, core: &Core, x: i32, y: i32, color: Color)
 	{
-		let frame = min(unsafe
-		{
-			(al_get_time() as f64 - self.offset) * 4.0
-		} as i32, self.x_tiles * self.y_tiles - 1);
-		
-		self.draw_frame(core, frame, x, y, core.map_rgb_f(1.0, 1.0, 1.0));
+		self.draw_frame(core, self.clips.get(0).get_frame(self.ticks), x, y, color);
 	}
 
-	pub fn draw_tinted(&self, core: &Core, x: i32, y: i32, color: Color)
+	pub fn draw_clip(&self, core: &Core, clip: &str, x: i32, y: i32, color: Color)
 	{
-		let frame = unsafe
-		{
-			(al_get_time() as f64 - self.offset) * 8.0
-		} as i32 % (self.x_tiles * self.y_tiles);
-		
-		self.draw_frame(core, frame, x, y, color);
+		self.draw_frame(core, self.get_clip(clip).get_frame(self.ticks), x, y, color);
 	}
 
 	pub fn draw_frame(&self, core: &Core, frame: i32, x: i32, y: i32, color: Color)
@@ -74,7 +274,9 @@ impl Sprite
 
 		let w = self.width as f32;
 		let h = self.height as f32;
-		
-		core.draw_tinted_bitmap_region(&self.base_bitmap, color, tile_x * w, tile_y * h, w, h, x as f32, y as f32, Flag::zero());
+		let sx = self.region_x as f32 + tile_x * w;
+		let sy = self.region_y as f32 + tile_y * h;
+
+		core.draw_tinted_bitmap_region(&*self.base_bitmap, color, sx, sy, w, h, x as f32, y as f32, Flag::zero());
 	}
 }
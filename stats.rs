@@ -0,0 +1,76 @@
+use allegro5::*;
+use allegro_font::*;
+
+use std::cmp::max;
+
+use event::*;
+use lang::Strings;
+
+pub struct Stats
+{
+	pub tiles_mined: i32,
+	pub tiles_collapsed: i32,
+	pub gems_collected: i32,
+	pub gem_value: i32,
+	pub demons_killed: i32,
+	pub torches_lost: i32,
+}
+
+impl Stats
+{
+	pub fn new() -> Stats
+	{
+		Stats
+		{
+			tiles_mined: 0,
+			tiles_collapsed: 0,
+			gems_collected: 0,
+			gem_value: 0,
+			demons_killed: 0,
+			torches_lost: 0,
+		}
+	}
+	
+	pub fn record(&mut self, event: &GameEvent)
+	{
+		match *event
+		{
+			TileDamaged{..} => (),
+			TileMined{..} => self.tiles_mined += 1,
+			TileCollapsed{..} => self.tiles_collapsed += 1,
+			GemCollected{color, ..} =>
+			{
+				self.gems_collected += 1;
+				self.gem_value += color.get_value();
+			},
+			DemonKilled{..} => self.demons_killed += 1,
+			TorchExtinguished{..} => self.torches_lost += 1,
+			PlayerDied{..} => (),
+		}
+	}
+	
+	pub fn draw(&self, core: &Core, font: &Font, strings: &Strings, dw: i32, dh: i32)
+	{
+		let lines = 
+		[
+			(strings.tr("Tiles mined:"), self.tiles_mined),
+			(strings.tr("Tiles collapsed:"), self.tiles_collapsed),
+			(strings.tr("Gems collected:"), self.gems_collected),
+			(strings.tr("Demons crushed:"), self.demons_killed),
+			(strings.tr("Torches lost:"), self.torches_lost),
+		];
+		
+		// Labels on the left, numbers lined up after the longest one
+		let width = lines.iter().fold(0, |w, &(label, _)| max(w, font.get_text_width(label)));
+		let x = dw / 2 - 80;
+		let mut y = dh / 2 + 50;
+		
+		let grey = core.map_rgb_f(0.7, 0.7, 0.7);
+		for &(label, value) in lines.iter()
+		{
+			core.draw_text(font, grey, x as f32, y as f32, AlignLeft, label);
+			core.draw_text(font, grey, (x + width + 8) as f32, y as f32, AlignLeft, format!("{}", value));
+			y += 10;
+		}
+	}
+}
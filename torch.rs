@@ -5,6 +5,7 @@ use allegro5::*;
 use world::{World, Cave, SupportType};
 use camera::Camera;
 use gfx::Gfx;
+use event::{EventQueue, TorchExtinguished};
 
 pub struct Torch
 {
@@ -30,7 +31,7 @@ impl Torch
 		}
 	}
 
-	pub fn update(&mut self, world: &World)
+	pub fn update(&mut self, world: &World, events: &mut EventQueue)
 	{
 		if self.dead
 		{
@@ -40,6 +41,7 @@ impl Torch
 		if world.colliding(self.x, self.y, self.w, self.h)
 		{
 			self.dead = true;
+			events.push(TorchExtinguished{x: self.x + self.w / 2, y: self.y + self.h / 2});
 			return;
 		}
 	}
@@ -0,0 +1,73 @@
+use std::io::TypeDirectory;
+use std::io::fs::{walk_dir, stat};
+
+/* How often to look at the directory, in ticks */
+static POLL_INTERVAL: i32 = 30;
+
+/* Notices files in a directory and its subdirectories being added or modified, for reloading
+ * assets while the game runs */
+pub struct AssetWatcher
+{
+	dir: Path,
+	mtimes: Vec<(Path, u64)>,
+	countdown: i32,
+}
+
+fn scan(dir: &Path) -> Vec<(Path, u64)>
+{
+	match walk_dir(dir)
+	{
+		Ok(paths) => paths.filter_map(|p|
+		{
+			match stat(&p)
+			{
+				Ok(ref st) if st.kind == TypeDirectory => None,
+				Ok(st) => Some((p, st.modified)),
+				Err(_) => None
+			}
+		}).collect(),
+		Err(e) =>
+		{
+			println!("Failed to read {}: {}", dir.display(), e);
+			Vec::new()
+		}
+	}
+}
+
+impl AssetWatcher
+{
+	pub fn new(dir: &str) -> AssetWatcher
+	{
+		let dir = Path::new(dir);
+		AssetWatcher
+		{
+			mtimes: scan(&dir),
+			dir: dir,
+			countdown: POLL_INTERVAL,
+		}
+	}
+
+	/* Returns the files that changed since the last poll */
+	pub fn poll(&mut self) -> Vec<Path>
+	{
+		self.countdown -= 1;
+		if self.countdown > 0
+		{
+			return Vec::new();
+		}
+		self.countdown = POLL_INTERVAL;
+
+		let mtimes = scan(&self.dir);
+		let mut changed = Vec::new();
+		for &(ref path, mtime) in mtimes.iter()
+		{
+			let old = self.mtimes.iter().find(|&&(ref p, _)| p == path).map(|&(_, t)| t);
+			if old != Some(mtime)
+			{
+				changed.push(path.clone());
+			}
+		}
+		self.mtimes = mtimes;
+		changed
+	}
+}
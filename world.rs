@@ -1,15 +1,19 @@
 use allegro5::*;
 use allegro_font::*;
+use allegro_primitives::*;
 
 use std::cmp::{min, max};
+use std::iter::range_inclusive;
 use std::num::abs;
 use camera::Camera;
+use event::{EventQueue, TileDamaged, TileMined, TileCollapsed};
 use num::Integer;
-use rand::{task_rng, Rng};
+use rand::Rng;
 use std::f32::INFINITY;
 use std::fmt;
 use torch::Torch;
 use sprite::Sprite;
+use debug::*;
 
 pub static TILE_SIZE: i32 = 32;
 pub static TILE_HEALTH: i32 = 32;
@@ -110,6 +114,42 @@ pub enum TileType
 	Surface,
 }
 
+/* What is left behind a tile once it is gone */
+#[deriving(Eq, Clone)]
+pub enum WallType
+{
+	NoWall,
+	DirtWall,
+	TurfWall,
+	CaveWall,
+}
+
+impl WallType
+{
+	fn get_frame(&self) -> Option<i32>
+	{
+		match *self
+		{
+			NoWall => None,
+			DirtWall => Some(0),
+			TurfWall => Some(4),
+			CaveWall => Some(7),
+		}
+	}
+	
+	/* Dug out walls are darker than the rock in front of them, so tunnels read as holes */
+	fn get_tint(&self) -> (f32, f32, f32)
+	{
+		match *self
+		{
+			NoWall => (0.0, 0.0, 0.0),
+			DirtWall => (0.4, 0.33, 0.3),
+			TurfWall => (0.35, 0.4, 0.3),
+			CaveWall => (1.0, 1.0, 1.0),
+		}
+	}
+}
+
 #[deriving(Clone)]
 pub struct Tile
 {
@@ -206,7 +246,7 @@ impl Tile
 		}
 	}
 
-	pub fn ground() -> Tile
+	pub fn ground<R: Rng>(rng: &mut R) -> Tile
 	{
 		Tile
 		{
@@ -215,7 +255,7 @@ impl Tile
 			health: TILE_HEALTH,
 			support: 4.0,
 			fall_state: 0,
-			has_gem: task_rng().gen_weighted_bool(5),
+			has_gem: rng.gen_weighted_bool(5),
 			demon_policy: MoveUp,
 			demon_value: INFINITY,
 			light: 0.0,
@@ -249,12 +289,15 @@ pub struct World
 	old_player_tx: uint,
 	old_player_ty: uint,
 	policy_done: bool, // if false, then we have a policy that is not yet converged
-	tile_sprite: Sprite
+	tile_sprite: Sprite,
+	explored: Vec<bool>,
+	walls: Vec<WallType>,
+	daylight: f32,
 }
 
 impl World
 {
-	pub fn new(core: &Core, width: uint, height: uint) -> World
+	pub fn new<R: Rng>(tile_sprite: Sprite, width: uint, height: uint, rng: &mut R) -> World
 	{
 		assert!(width > 10);
 		assert!(height > 10);
@@ -280,7 +323,7 @@ impl World
 					}
 					else
 					{
-						Tile::ground()
+						Tile::ground(&mut *rng)
 					}
 				);
 			}
@@ -296,30 +339,50 @@ impl World
 			old_player_tx: 0,
 			old_player_ty: 0,
 			policy_done: true, // Has to be true, since we have no running policy yet
-			tile_sprite: Sprite::new(core, "data/tiles.png", 32, 32)
+			tile_sprite: tile_sprite,
+			// The sky and the surface are known from the start
+			explored: Vec::from_fn(width * height, |idx| idx / width <= SURFACE_HEIGHT as uint),
+			walls: Vec::from_fn(width * height, |idx|
+			{
+				let row = idx / width;
+				if row < SURFACE_HEIGHT as uint
+				{
+					NoWall
+				}
+				else if row == SURFACE_HEIGHT as uint
+				{
+					TurfWall
+				}
+				else
+				{
+					DirtWall
+				}
+			}),
+			daylight: 1.0,
 		}
 	}
 	
-	pub fn add_caves(&mut self, demon_callback: |(i32, i32)|, gem_callback: |bool, (i32, i32)|) -> (i32, i32)
+	pub fn add_caves<R: Rng>(&mut self, rng: &mut R, demon_callback: |(i32, i32)|, gem_callback: |bool, (i32, i32)|) -> (i32, i32)
 	{
 		let mut phil_loc = (0, 0);
 		for _ in range(0, 30)
 		{
-			let cave_y = task_rng().gen_range(20, self.height - 6);
-			let cave_width = task_rng().gen_range(2, 5u);
-			let cave_x = task_rng().gen_range(0, self.width - cave_width);
+			let cave_y = rng.gen_range(20, self.height - 6);
+			let cave_width = rng.gen_range(2, 5u);
+			let cave_x = rng.gen_range(0, self.width - cave_width);
 			
 			let mut num_demons = 0;
 			let mut gem_spots = Vec::new();
 			
 			for x in range(cave_x, cave_x + cave_width)
 			{
-				let y1 = task_rng().gen_range(cave_y - 3, cave_y);
-				let y2 = y1 + task_rng().gen_range(3, 5u);
-				let mut add_gem = task_rng().gen_weighted_bool(2);
+				let y1 = rng.gen_range(cave_y - 3, cave_y);
+				let y2 = y1 + rng.gen_range(3, 5u);
+				let mut add_gem = rng.gen_weighted_bool(2);
 				
 				for y in range(y1, y2)
 				{
+					*self.walls.get_mut(y * self.width + x) = CaveWall;
 					if y == y1
 					{
 						*self.get_tile_mut(x, y) = Tile::cave_ceil();
@@ -341,7 +404,7 @@ impl World
 							add_gem = false;
 						}
 						
-						if !task_rng().gen_weighted_bool(cave_y / 30)
+						if !rng.gen_weighted_bool(cave_y / 30)
 						{
 							num_demons += 1;
 							demon_callback(center);
@@ -361,6 +424,21 @@ impl World
 		phil_loc
 	}
 	
+	pub fn set_tile_sprite(&mut self, tile_sprite: Sprite)
+	{
+		self.tile_sprite = tile_sprite;
+	}
+	
+	pub fn get_width(&self) -> uint
+	{
+		self.width
+	}
+	
+	pub fn get_height(&self) -> uint
+	{
+		self.height
+	}
+	
 	pub fn get_pixel_width(&self) -> i32
 	{
 		self.width as i32 * TILE_SIZE
@@ -395,6 +473,28 @@ impl World
 				
 				let damage = (TILE_HEALTH - tile.health) * 3 / TILE_HEALTH;
 				
+				// The wall shows through empty tiles, supports and the gap above a falling tile
+				if tile.collision != Solid || tile.fall_state != 0
+				{
+					let wall = self.walls.get(idx);
+					match wall.get_frame()
+					{
+						Some(frame) =>
+						{
+							let (r, g, b) = wall.get_tint();
+							let wall_y = ty as i32 * sz - camera.y;
+							self.tile_sprite.draw_frame(core, frame, x, wall_y, core.map_rgb_f(r * l, g * l, b * l));
+						}
+						None => ()
+					}
+				}
+				
+				// The backdrop shows through the sky, the wall through caves
+				if tile.tile_type == Sky || tile.tile_type == Cave
+				{
+					continue;
+				}
+				
 				let frame = match tile.tile_type
 				{
 					Sky => 3,
@@ -407,72 +507,185 @@ impl World
 				};
 				
 				self.tile_sprite.draw_frame(core, frame, x, y, color);
-				
-				//~ prim.draw_filled_rectangle(x, y, x + sz as f32, y + sz as f32, color);
-				
-				//~ if tile.collision == Solid
-				//~ {
-					//~ let g = 0.5 * tile.health as f32 / TILE_HEALTH as f32;
-					//~ prim.draw_filled_rectangle(x, y, x + sz as f32, y + sz as f32, core.map_rgb_f(g, g, g));
-					//~ core.draw_text(font, core.map_rgb_f(1.0, 1.0, 1.0), x, y, AlignLeft, format!("{}", tile.support));
-				//~ }
-				//~ else if tile.collision == Support
-				//~ {
-					//~ prim.draw_filled_rectangle(x, y, x + sz as f32, y + sz as f32, core.map_rgb_f(0.5, 1.0, 1.0));
-					//~ core.draw_text(font, core.map_rgb_f(1.0, 1.0, 1.0), x, y, AlignLeft, format!("{}", tile.support));
-				//~ }
-				
-				if tile.collision != Solid
-				{
-					//~ core.draw_text(font, core.map_rgb_f(1.0, 1.0, 1.0), x, y, AlignLeft, format!("{}", tile.demon_policy));
-					//~ core.draw_text(font, core.map_rgb_f(1.0, 1.0, 1.0), x, y + 8.0, AlignLeft, format!("{}", tile.demon_value));
-				}
 			}
 		}
 	}
 	
-	pub fn update(&mut self, camera: &mut Camera, torches: &[Torch], player_x: i32, player_y: i32, player_w: i32, player_h: i32) -> bool
+	pub fn draw_debug(&self, core: &Core, prim: &PrimitivesAddon, font: &Font, camera: &Camera, mode: DebugMode)
 	{
-		let mut any_falling = false;
-		for y in range(1, self.height - 1).rev()
+		if mode == DebugOff
 		{
-			for x in range(0, self.width)
+			return;
+		}
+		
+		let sz = TILE_SIZE;
+		let min_tx = min(max(camera.x / sz, 0) as uint, self.width);
+		let min_ty = min(max(camera.y / sz, 0) as uint, self.height);
+		let max_tx = min(min_tx + (camera.width / sz) as uint + 2, self.width);
+		let max_ty = min(min_ty + (camera.height / sz) as uint + 2, self.height);
+		
+		let white = core.map_rgb_f(1.0, 1.0, 1.0);
+		let tint = |r: f32, g: f32, b: f32, a: f32| core.map_rgba_f(r * a, g * a, b * a, a);
+		
+		for ty in range(min_ty, max_ty)
+		{
+			for tx in range(min_tx, max_tx)
 			{
-				any_falling |= self.get_tile(x, y).fall_state != 0;
+				let tile = self.get_tile(tx, ty);
 				
-				// Deal with supports
-				if self.get_tile(x, y).collision != Empty &&
-				   self.get_tile(x, y).tile_type != CaveCeiling &&
-				   self.get_tile(x, y).tile_type != Surface
+				let x = (tx as i32 * sz - camera.x) as f32;
+				let y = (ty as i32 * sz - camera.y + tile.fall_state) as f32;
+				let x2 = x + sz as f32;
+				let y2 = y + sz as f32;
+				
+				match mode
 				{
-					let mut sup = 0.0f32;
-					let mut num_supports = 0;
-					if x > 0
+					DebugSupport =>
 					{
-						let tile = self.get_tile(x - 1, y);
-						sup = sup.max(tile.support * (tile.health as f32 / TILE_HEALTH as f32) - 1.0);
-						if tile.support > 0.0
+						if tile.collision != Empty
 						{
-							num_supports += 1;
+							let f = (tile.support / 4.0).min(1.0).max(0.0);
+							prim.draw_filled_rectangle(x, y, x2, y2, tint(1.0 - f, f, 0.0, 0.5));
+							core.draw_text(font, white, x + 2.0, y + 2.0, AlignLeft, format!("{:.1f}", tile.support));
 						}
-					}
-					if x < self.width - 1
+					},
+					DebugLight =>
 					{
-						let tile = self.get_tile(x + 1, y);
-						sup = sup.max(tile.support * (tile.health as f32 / TILE_HEALTH as f32) - 1.0);
-						if tile.support > 0.0
+						prim.draw_filled_rectangle(x, y, x2, y2, tint(1.0, 1.0, 0.0, 0.5 * tile.light));
+						core.draw_text(font, white, x + 2.0, y + 2.0, AlignLeft, format!("{:.2f}", tile.light));
+					},
+					DebugDemons =>
+					{
+						if tile.collision != Solid && tile.demon_value < INFINITY
 						{
-							num_supports += 1;
+							let (dx, dy) = tile.demon_policy.get_shift();
+							let cx = x + (sz / 2) as f32;
+							let cy = y + (sz / 2) as f32;
+							let ex = cx + (dx * sz / 3) as f32;
+							let ey = cy + (dy * sz / 3) as f32;
+							let red = core.map_rgb_f(1.0, 0.2, 0.2);
+							prim.draw_line(cx, cy, ex, ey, red, 1.0);
+							prim.draw_filled_rectangle(ex - 1.5, ey - 1.5, ex + 1.5, ey + 1.5, red);
+							core.draw_text(font, white, x + 2.0, y + 2.0, AlignLeft, format!("{}", tile.demon_value));
 						}
-					}
+					},
+					DebugHealth =>
+					{
+						if tile.health < TILE_HEALTH
+						{
+							let f = tile.health as f32 / TILE_HEALTH as f32;
+							prim.draw_filled_rectangle(x + 2.0, y2 - 5.0, x + 2.0 + (sz - 4) as f32 * f, y2 - 2.0, tint(1.0 - f, f, 0.0, 1.0));
+							core.draw_text(font, white, x + 2.0, y + 2.0, AlignLeft, format!("{}", tile.health));
+						}
+					},
+					DebugCollision =>
 					{
-						let tile = self.get_tile(x, y + 1);
-						sup = sup.max(tile.support * (tile.health as f32 / TILE_HEALTH as f32));
-						if tile.support > 0.0
+						match tile.collision
 						{
-							num_supports += 1;
+							Solid => prim.draw_rectangle(x + 0.5, y + 0.5, x2 - 0.5, y2 - 0.5, tint(1.0, 0.0, 0.0, 0.8), 1.0),
+							Support => prim.draw_filled_rectangle(x, y, x2, y2, tint(0.0, 1.0, 1.0, 0.4)),
+							Empty => ()
 						}
+					},
+					DebugOff => ()
+				}
+			}
+		}
+	}
+	
+	pub fn get_daylight(&self) -> f32
+	{
+		self.daylight
+	}
+	
+	/* How bright the sky is, from 0 (pitch black) to 1 (noon) */
+	pub fn set_daylight(&mut self, daylight: f32)
+	{
+		self.daylight = daylight;
+		self.need_new_light = true;
+	}
+	
+	pub fn is_explored(&self, x: i32, y: i32) -> bool
+	{
+		self.get_tile_coords(x, y).map_or(false, |(tx, ty)| *self.explored.get(ty * self.width + tx))
+	}
+	
+	/* Anything lit within the view counts as seen */
+	pub fn update_explored(&mut self, camera: &Camera)
+	{
+		let sz = TILE_SIZE;
+		let min_tx = min(max(camera.x / sz, 0) as uint, self.width);
+		let min_ty = min(max(camera.y / sz, 0) as uint, self.height);
+		let max_tx = min(min_tx + (camera.width / sz) as uint + 2, self.width);
+		let max_ty = min(min_ty + (camera.height / sz) as uint + 2, self.height);
+		
+		for ty in range(min_ty, max_ty)
+		{
+			for tx in range(min_tx, max_tx)
+			{
+				let idx = ty * self.width + tx;
+				if self.tiles.get(idx).light > 0.05
+				{
+					*self.explored.get_mut(idx) = true;
+				}
+			}
+		}
+	}
+	
+	/* Draws the explored tiles in the given range, one scale x scale block per tile */
+	pub fn draw_map(&self, core: &Core, prim: &PrimitivesAddon, x: i32, y: i32, scale: i32, min_tx: uint, min_ty: uint, max_tx: uint, max_ty: uint)
+	{
+		let max_tx = min(max_tx, self.width);
+		let max_ty = min(max_ty, self.height);
+		
+		for ty in range(min_ty, max_ty)
+		{
+			for tx in range(min_tx, max_tx)
+			{
+				let idx = ty * self.width + tx;
+				if !*self.explored.get(idx)
+				{
+					continue;
+				}
+				
+				let color = match self.tiles.get(idx).tile_type
+				{
+					Sky => core.map_rgb_f(0.3, 0.5, 0.8),
+					Surface => core.map_rgb_f(0.3, 0.6, 0.2),
+					Ground => core.map_rgb_f(0.45, 0.3, 0.2),
+					CaveCeiling => core.map_rgb_f(0.35, 0.25, 0.2),
+					Cave => if *self.walls.get(idx) == CaveWall
+					{
+						core.map_rgb_f(0.1, 0.1, 0.1)
 					}
+					else
+					{
+						core.map_rgb_f(0.2, 0.15, 0.12)
+					},
+					SupportType => core.map_rgb_f(0.8, 0.7, 0.3),
+					Bottom => core.map_rgb_f(0.25, 0.25, 0.25),
+				};
+				
+				let x1 = (x + (tx - min_tx) as i32 * scale) as f32;
+				let y1 = (y + (ty - min_ty) as i32 * scale) as f32;
+				prim.draw_filled_rectangle(x1, y1, x1 + scale as f32, y1 + scale as f32, color);
+			}
+		}
+	}
+	
+	pub fn update(&mut self, torches: &[Torch], player_x: i32, player_y: i32, player_w: i32, player_h: i32, events: &mut EventQueue) -> bool
+	{
+		let mut any_falling = false;
+		for y in range(1, self.height - 1).rev()
+		{
+			for x in range(0, self.width)
+			{
+				any_falling |= self.get_tile(x, y).fall_state != 0;
+				
+				// Deal with supports
+				if self.can_fall(x, y)
+				{
+					let (sup, num_supports) = self.compute_support(x, y);
 
 					if sup + num_supports as f32 * 0.4 <= 1.0 && self.get_tile(x, y).fall_state == 0
 					{
@@ -497,11 +710,7 @@ impl World
 						if self.get_tile(x, y).fall_state >= 0
 						{
 							self.get_tile_mut(x, y).fall_state = 0;
-							camera.jolt(5.0);
-						}
-						else
-						{
-							camera.jolt(2.0);
+							events.push(TileCollapsed{x: x as i32 * TILE_SIZE + TILE_SIZE / 2, y: y as i32 * TILE_SIZE + TILE_SIZE / 2});
 						}
 					}
 				}
@@ -511,19 +720,22 @@ impl World
 		// Deal with lights
 		if self.need_new_light
 		{
-			// Darken under-surface
-			for y in range(SURFACE_HEIGHT as uint, self.height)
+			// Darken under-surface, the sky gets whatever the daylight is
+			for y in range(0, self.height)
 			{
+				let light = if y < SURFACE_HEIGHT as uint { self.daylight } else { 0.0 };
 				for x in range(0, self.width)
 				{
-					self.get_tile_mut(x, y).light = 0.0;
+					self.get_tile_mut(x, y).light = light;
 				}
 			}
 			
-			// Surface lights
+			// Surface lights, reaching less far down at night
+			let daylight = self.daylight;
+			let reach = (5.0 * daylight).ceil() as uint;
 			for x in range(0, self.width)
 			{
-				self.light_ray(x, SURFACE_HEIGHT as uint - 1, x, SURFACE_HEIGHT as uint + 5);
+				self.light_ray(x, SURFACE_HEIGHT as uint - 1, x, SURFACE_HEIGHT as uint + reach, daylight);
 			}
 			
 			for torch in torches.iter()
@@ -537,22 +749,22 @@ impl World
 					
 					for x in range(x1, x2 + 1)
 					{
-						self.light_ray(tx, ty, x, y1);
+						self.light_ray(tx, ty, x, y1, 1.0);
 					}
 
 					for x in range(x1, x2 + 1).rev()
 					{
-						self.light_ray(tx, ty, x, y2);
+						self.light_ray(tx, ty, x, y2, 1.0);
 					}
 
 					for y in range(y1 + 1, y2)
 					{
-						self.light_ray(tx, ty, x1, y);
+						self.light_ray(tx, ty, x1, y, 1.0);
 					}
 
 					for y in range(y1 + 1, y2)
 					{
-						self.light_ray(tx, ty, x2, y);
+						self.light_ray(tx, ty, x2, y, 1.0);
 					}
 				});
 			}
@@ -663,6 +875,84 @@ impl World
 		any_falling
 	}
 	
+	/* Whether the tile is held up by its neighbours, rather than being open or fixed in place */
+	fn can_fall(&self, x: uint, y: uint) -> bool
+	{
+		self.get_tile(x, y).collision != Empty &&
+		self.get_tile(x, y).tile_type != CaveCeiling &&
+		self.get_tile(x, y).tile_type != Surface
+	}
+	
+	/* The best support the tile gets from the tiles beside and below it, and how many of them have any */
+	fn compute_support(&self, x: uint, y: uint) -> (f32, i32)
+	{
+		let mut sup = 0.0f32;
+		let mut num_supports = 0;
+		if x > 0
+		{
+			let tile = self.get_tile(x - 1, y);
+			sup = sup.max(tile.support * (tile.health as f32 / TILE_HEALTH as f32) - 1.0);
+			if tile.support > 0.0
+			{
+				num_supports += 1;
+			}
+		}
+		if x < self.width - 1
+		{
+			let tile = self.get_tile(x + 1, y);
+			sup = sup.max(tile.support * (tile.health as f32 / TILE_HEALTH as f32) - 1.0);
+			if tile.support > 0.0
+			{
+				num_supports += 1;
+			}
+		}
+		{
+			let tile = self.get_tile(x, y + 1);
+			sup = sup.max(tile.support * (tile.health as f32 / TILE_HEALTH as f32));
+			if tile.support > 0.0
+			{
+				num_supports += 1;
+			}
+		}
+		(sup, num_supports)
+	}
+	
+	/* Center of the closest tile, within reach tiles of the point, that would fall if its support
+	 * dropped by margin, along with how close it is to falling (1 when it's right at the edge) */
+	pub fn get_nearest_creaking(&self, x: i32, y: i32, reach: i32, margin: f32) -> Option<((i32, i32), f32)>
+	{
+		let ptx = x.div_floor(&TILE_SIZE);
+		let pty = y.div_floor(&TILE_SIZE);
+		let mut best = None;
+		let mut best_dist = 0;
+		for ty in range_inclusive(max(pty - reach, 1), min(pty + reach, self.height as i32 - 2))
+		{
+			for tx in range_inclusive(max(ptx - reach, 0), min(ptx + reach, self.width as i32 - 1))
+			{
+				let (tx, ty) = (tx as uint, ty as uint);
+				if !self.can_fall(tx, ty) || self.get_tile(tx, ty).fall_state != 0 || self.get_tile(tx, ty + 1).collision != Empty
+				{
+					continue;
+				}
+				let (sup, num_supports) = self.compute_support(tx, ty);
+				let slack = sup + num_supports as f32 * 0.4 - 1.0;
+				if slack <= 0.0 || slack > margin
+				{
+					continue;
+				}
+				let cx = tx as i32 * TILE_SIZE + TILE_SIZE / 2;
+				let cy = ty as i32 * TILE_SIZE + TILE_SIZE / 2;
+				let dist = (cx - x) * (cx - x) + (cy - y) * (cy - y);
+				if best.is_none() || dist < best_dist
+				{
+					best = Some(((cx, cy), 1.0 - slack / margin));
+					best_dist = dist;
+				}
+			}
+		}
+		best
+	}
+	
 	pub fn get_tile<'l>(&'l self, tx: uint, ty: uint) -> &'l Tile
 	{
 		self.tiles.get(ty * self.width + tx)
@@ -704,7 +994,7 @@ impl World
 		 (y + h / 2).div_floor(&TILE_SIZE) * TILE_SIZE + TILE_SIZE / 2)
 	}
 
-	pub fn light_ray(&mut self, stx: uint, sty: uint, dtx: uint, dty: uint)
+	pub fn light_ray(&mut self, stx: uint, sty: uint, dtx: uint, dty: uint, intensity: f32)
 	{
 		let dx = dtx as i32 - stx as i32;
 		let dy = dty as i32 - sty as i32;
@@ -722,7 +1012,7 @@ impl World
 			{
 				let delta_y = dy * delta_x / dx;				
 				let y = sty as i32 + delta_y;
-				let light = (1.0 / (0.1 + (delta_x as f32) * (delta_x as f32) + (delta_y as f32) * (delta_y as f32))).min(1.0);
+				let light = (1.0 / (0.1 + (delta_x as f32) * (delta_x as f32) + (delta_y as f32) * (delta_y as f32))).min(1.0) * intensity;
 				
 				let tile = self.get_tile_mut(x as uint, y as uint);
 				tile.light = tile.light.max(light);
@@ -742,7 +1032,7 @@ impl World
 			{
 				let delta_x = dx * delta_y / dy;				
 				let x = stx as i32 + delta_x;
-				let light = (1.0 / (0.1 + (delta_x as f32) * (delta_x as f32) + (delta_y as f32) * (delta_y as f32))).min(1.0);
+				let light = (1.0 / (0.1 + (delta_x as f32) * (delta_x as f32) + (delta_y as f32) * (delta_y as f32))).min(1.0) * intensity;
 				
 				let tile = self.get_tile_mut(x as uint, y as uint);
 				tile.light = tile.light.max(light);
@@ -756,6 +1046,55 @@ impl World
 		}
 	}
 	
+	/* Number of solid tiles strictly between the two points, sampled every half tile */
+	pub fn count_solid_between(&self, x1: i32, y1: i32, x2: i32, y2: i32) -> i32
+	{
+		let start = self.get_tile_coords(x1, y1);
+		let end = self.get_tile_coords(x2, y2);
+		let steps = max(abs(x2 - x1), abs(y2 - y1)) / (TILE_SIZE / 2);
+		let mut last = start;
+		let mut count = 0;
+		for i in range(1, steps)
+		{
+			let coords = self.get_tile_coords(x1 + (x2 - x1) * i / steps, y1 + (y2 - y1) * i / steps);
+			if coords != last && coords != end
+			{
+				match coords
+				{
+					Some((tx, ty)) if self.get_tile(tx, ty).collision == Solid => count += 1,
+					_ => ()
+				}
+			}
+			last = coords;
+		}
+		count
+	}
+	
+	/* Center of the falling tile closest to the point */
+	pub fn get_nearest_falling(&self, x: i32, y: i32) -> Option<(i32, i32)>
+	{
+		let mut best = None;
+		let mut best_dist = 0;
+		for ty in range(0, self.height)
+		{
+			for tx in range(0, self.width)
+			{
+				if self.get_tile(tx, ty).fall_state != 0
+				{
+					let cx = tx as i32 * TILE_SIZE + TILE_SIZE / 2;
+					let cy = ty as i32 * TILE_SIZE + TILE_SIZE / 2;
+					let dist = (cx - x) * (cx - x) + (cy - y) * (cy - y);
+					if best.is_none() || dist < best_dist
+					{
+						best = Some((cx, cy));
+						best_dist = dist;
+					}
+				}
+			}
+		}
+		best
+	}
+	
 	pub fn get_tile_coords(&self, x: i32, y: i32) -> Option<(uint, uint)>
 	{
 		let tx = x.div_floor(&TILE_SIZE);
@@ -909,12 +1248,13 @@ impl World
 		}
 	}
 
-	pub fn mine(&mut self, x: i32, y: i32, dtx: i32, dty: i32) -> Option<(i32, i32)>
+	pub fn mine(&mut self, x: i32, y: i32, dtx: i32, dty: i32, events: &mut EventQueue) -> Option<(i32, i32)>
 	{
 		let tx = (x + TILE_SIZE / 2).div_floor(&TILE_SIZE) + dtx;
 		let ty = (y + TILE_SIZE / 2).div_floor(&TILE_SIZE) + dty;
 		
 		let mut removed = false;
+		let mut damaged = false;
 		
 		let ret = if tx >= 0 && tx < self.width as i32 && ty >= 0 && ty < self.height as i32
 		{
@@ -926,6 +1266,7 @@ impl World
 			   tile.tile_type == CaveCeiling
 			{
 				tile.health -= 2;
+				damaged = true;
 				if tile.health <= 0
 				{
 					let ret = if tile.has_gem
@@ -959,6 +1300,11 @@ impl World
 		{
 			self.need_new_policy = true;
 			self.need_new_light = true;
+			events.push(TileMined{x: tx * TILE_SIZE + TILE_SIZE / 2, y: ty * TILE_SIZE + TILE_SIZE / 2});
+		}
+		else if damaged
+		{
+			events.push(TileDamaged{x: tx * TILE_SIZE + TILE_SIZE / 2, y: ty * TILE_SIZE + TILE_SIZE / 2});
 		}
 		
 		ret